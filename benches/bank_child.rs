@@ -0,0 +1,61 @@
+//Bank::child shares the parent's towers, applying the child's block
+//copies them. compares copying the towers backed by VoteStack with the
+//VecDeque they used to be backed by. run with cargo bench --bench
+//bank_child
+use std::collections::VecDeque;
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::Instant;
+use tower_sim::bank::{Bank, DEFAULT_SEED, NUM_NODES};
+use tower_sim::tower::{Vote, DEPTH};
+
+const ROUNDS: u32 = 2000;
+
+//the tower before VoteStack
+#[derive(Clone)]
+struct DequeTower {
+    _votes: VecDeque<Vote>,
+    _root: Vote,
+    _base: u64,
+}
+
+fn main() {
+    let mut bank = Bank::zero(DEFAULT_SEED);
+    for id in 0..NUM_NODES {
+        for slot in 1..=DEPTH as u64 {
+            Arc::make_mut(&mut bank.nodes)[id]
+                .apply(&Vote::new(slot))
+                .unwrap();
+        }
+    }
+    bank.frozen = true;
+    let deques: Vec<DequeTower> = bank
+        .nodes
+        .iter()
+        .map(|t| DequeTower {
+            _votes: t.votes.iter().copied().collect(),
+            _root: t.root,
+            _base: t.base,
+        })
+        .collect();
+
+    let start = Instant::now();
+    for i in 0..ROUNDS {
+        black_box(bank.child(DEPTH as u64 + 1 + i as u64));
+    }
+    let child = start.elapsed() / ROUNDS;
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(bank.nodes.to_vec());
+    }
+    let stack = start.elapsed() / ROUNDS;
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(deques.clone());
+    }
+    let deque = start.elapsed() / ROUNDS;
+    println!(
+        "{} towers: Bank::child {:?}, VoteStack clone {:?}, VecDeque clone {:?}",
+        NUM_NODES, child, stack, deque
+    );
+}
@@ -0,0 +1,40 @@
+//incremental fork weight updates, done by Banks::apply, against the
+//full rebuild they replaced. run with cargo bench --bench fork_weights
+use std::time::{Duration, Instant};
+use tower_sim::bank::{Banks, BlockBuilder, NUM_NODES};
+use tower_sim::tower::Slot;
+
+const BLOCKS: Slot = 512;
+
+fn main() {
+    let mut banks = Banks::default();
+    let mut apply = Duration::ZERO;
+    let mut rebuild = Duration::ZERO;
+    let mut parent = 0;
+    for slot in 1..=BLOCKS {
+        //every node votes on the parent of the even slots, the odd
+        //slots are empty forks off the same parent
+        let mut block = BlockBuilder::default().slot(slot).parent(parent);
+        if slot % 2 == 0 {
+            for id in 0..NUM_NODES {
+                block = block.vote(id, parent);
+            }
+        }
+        let start = Instant::now();
+        banks.apply(&block.build()).unwrap();
+        apply += start.elapsed();
+        let start = Instant::now();
+        banks.build_fork_weights();
+        rebuild += start.elapsed();
+        if slot % 2 == 0 {
+            parent = slot;
+        }
+    }
+    println!(
+        "{} banks left: Banks::apply with the incremental update {:?}/block, \
+         build_fork_weights alone {:?}/block",
+        banks.fork_map.len(),
+        apply / BLOCKS as u32,
+        rebuild / BLOCKS as u32
+    );
+}
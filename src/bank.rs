@@ -1,18 +1,87 @@
+use crate::config::Config;
+use crate::fork_choice::{ForkChoice, HeaviestFork};
 use crate::node::THRESHOLD;
-use crate::tower::{Slot, Tower, Vote};
+use crate::snapshot::{BankSnapshot, BanksSnapshot, SnapshotError};
+use crate::tower::{Slot, Tower, TowerError, Vote, DEPTH, LOCKOUT_BASE};
 use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 
 pub const NUM_NODES: usize = 997;
 pub const SUBCOMMITTEE_EPOCH: usize = 64;
 pub const SUBCOMMITTEE_SIZE: usize = 200;
+const _: () = assert!(SUBCOMMITTEE_SIZE <= NUM_NODES);
+pub const DEFAULT_SEED: u64 = 0;
+//(numerator, denominator) of the fraction of the nodes that is a
+//supermajority
+pub const SUPERMAJORITY: (u64, u64) = (2, 3);
 pub type ID = usize;
+//hash used to draw subcommittees from the seed and epoch. each draw
+//hashes the previous one, so the hash shouldn't have short cycles, e.g.
+//xorshift maps 0 to 0. see MAX_DRAWS
+pub type HashFn = fn(u64) -> u64;
+//draws with a HashFn before calc_subcommittee_with gives up on it
+pub const MAX_DRAWS: usize = 64 * NUM_NODES;
 
+//stake of each node by id. every node has a stake of 1 unless the
+//config gives a distribution, e.g. from Network::from_stake_csv
+pub type Stakes = Arc<Vec<u64>>;
+
+pub fn equal_stakes() -> Stakes {
+    Arc::new(vec![1; NUM_NODES])
+}
+
+//a supermajority is more than this much of the total stake
+pub fn supermajority_stake(total: usize, ratio: (u64, u64)) -> usize {
+    (total as u128 * ratio.0 as u128 / ratio.1 as u128) as usize
+}
+
+//more than this much stake leaves the rest short of a supermajority
+pub fn minority_stake(total: usize, ratio: (u64, u64)) -> usize {
+    total - supermajority_stake(total, ratio) - 1
+}
+
+//a supermajority is more than this many nodes, when every node has
+//the same stake
+pub fn supermajority_nodes(ratio: (u64, u64)) -> usize {
+    supermajority_stake(NUM_NODES, ratio)
+}
+
+//more than this many nodes leaves the rest short of a supermajority,
+//when every node has the same stake
+pub fn minority_nodes(ratio: (u64, u64)) -> usize {
+    minority_stake(NUM_NODES, ratio)
+}
+
+//DefaultHasher output may change between rust versions, supply a
+//fixed hash such as splitmix64 for draws that are stable across them
+pub fn default_hash(val: u64) -> u64 {
+    let mut h = DefaultHasher::new();
+    val.hash(&mut h);
+    h.finish()
+}
+
+//default_hash for fields serde skips
+pub(crate) fn default_hash_fn() -> HashFn {
+    default_hash
+}
+
+pub fn splitmix64(val: u64) -> u64 {
+    let mut z = val.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct Subcommittee {
     //the current primary and secondary
+    #[serde(serialize_with = "crate::snapshot::serialize_sorted")]
     pub primary: HashSet<ID>,
+    #[serde(serialize_with = "crate::snapshot::serialize_sorted")]
     pub secondary: HashSet<ID>,
     // number of times supermajority roots have increased
     // this squashes ranges of increases into 1
@@ -20,41 +89,212 @@ pub struct Subcommittee {
     pub parent_num_super_roots: usize,
     pub super_root: Slot,
     pub parent_super_root: Slot,
+    //committee draws are a function of the seed and the epoch
+    pub seed: u64,
+    //restored from the config when a snapshot is loaded
+    #[serde(skip, default = "default_hash_fn")]
+    pub hash: HashFn,
+    //number of nodes drawn for the primary and secondary
+    pub size: usize,
+    //jaccard distance of the set replaced by the last rotation
+    pub turnover: f64,
 }
+#[derive(Clone)]
 pub struct Bank {
-    pub nodes: Vec<Tower>,
+    //a frozen bank's towers don't change, so clones of it share them
+    pub nodes: Arc<Vec<Tower>>,
     pub slot: Slot,
     pub parent: Slot,
     pub frozen: bool,
     pub children: Vec<Slot>,
     pub subcom: Subcommittee,
+    //nodes that submitted conflicting votes in this bank's block
+    pub equivocations: Vec<ID>,
+    //same for every bank, not saved in snapshots
+    pub supermajority: (u64, u64),
+    pub stakes: Stakes,
 }
 
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Block {
     pub slot: Slot,
     pub parent: Slot,
     pub votes: Vec<(ID, Vec<Vote>)>,
 }
 
+/// Chained construction of a `Block`, mostly for tests.
+/// Votes for the same node are kept in one list in the order they are added.
+#[derive(Default)]
+pub struct BlockBuilder {
+    slot: Slot,
+    parent: Slot,
+    votes: Vec<(ID, Vec<Vote>)>,
+}
+
+impl BlockBuilder {
+    pub fn slot(mut self, slot: Slot) -> Self {
+        self.slot = slot;
+        self
+    }
+    pub fn parent(mut self, parent: Slot) -> Self {
+        self.parent = parent;
+        self
+    }
+    //a new vote with the lockout Node::votes sends
+    pub fn vote(self, id: ID, slot: Slot) -> Self {
+        self.vote_with_lockout(id, slot, LOCKOUT_BASE)
+    }
+    pub fn vote_with_lockout(mut self, id: ID, slot: Slot, lockout: u64) -> Self {
+        let vote = Vote { slot, lockout };
+        match self.votes.iter_mut().find(|(x, _)| *x == id) {
+            Some((_, votes)) => votes.push(vote),
+            None => self.votes.push((id, vec![vote])),
+        }
+        self
+    }
+    pub fn build(self) -> Block {
+        assert!(
+            self.parent < self.slot,
+            "block parent {} is not below slot {}",
+            self.parent,
+            self.slot
+        );
+        Block {
+            slot: self.slot,
+            parent: self.parent,
+            votes: self.votes,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ApplyError {
+    //the parent already has the max number of children
+    TooManyChildren {
+        parent: Slot,
+        children: usize,
+    },
+    //the slot is already in the banks
+    DuplicateSlot(Slot),
+    //the parent is not in the banks, never applied or gc'd
+    MissingParent {
+        slot: Slot,
+        parent: Slot,
+    },
+    //the node voted on a slot that is not in the block's fork
+    VoteNotInFork {
+        id: ID,
+        slot: Slot,
+    },
+    //the slot is at or below its parent's
+    NotAfterParent {
+        slot: Slot,
+        parent: Slot,
+    },
+    //votes from an id that isn't one of the NUM_NODES nodes
+    UnknownNode(ID),
+    //a vote's lockout isn't the tower base every new vote starts with
+    InvalidLockout {
+        id: ID,
+        vote: Vote,
+    },
+    //apply_votes: the node's tower rejected the vote
+    Tower {
+        id: ID,
+        vote: Vote,
+        error: TowerError,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReplayError {
+    //the slot is already in the banks or earlier in the stream
+    DuplicateSlot(Slot),
+    //the block's parent never appeared in the banks or the stream
+    MissingParent { slot: Slot, parent: Slot },
+    Apply(ApplyError),
+}
+
+#[derive(Clone)]
 pub struct Banks {
     pub fork_map: HashMap<Slot, Bank>,
+    //banks pruned from fork_map within retain_depth slots of lowest_root,
+    //they don't count towards the fork weights
+    history: BTreeMap<Slot, Bank>,
+    pub retain_depth: usize,
     pub fork_weights: HashMap<Slot, usize>,
     pub lowest_root: Vote,
+    //every (slot, node) equivocation observed since genesis
+    pub equivocations: Vec<(Slot, ID)>,
+    //slots between a slot being produced and it becoming the lowest root
+    //or an ancestor of it, for every rooted slot
+    pub root_latencies: HashMap<Slot, usize>,
+    //blocks on a parent with this many children are rejected
+    pub max_children: Option<usize>,
+    //most children any bank has had
+    pub max_children_seen: usize,
+    //most banks in a branch pruned by gc without being rooted
+    pub max_orphan_depth: usize,
+    //slots on the rooted fork from genesis to lowest_root, the banks
+    //below lowest_root are gc'd so it's counted as the root advances
+    rooted_chain_length: usize,
+    //lockouts of base^threshold need 2/3+ of the nodes to confirm a slot
+    pub threshold: usize,
+    //fraction of the stake that is a supermajority, 2/3 by default
+    pub supermajority: (u64, u64),
+    //the fork weights add up the stake of the latest votes
+    pub stakes: Stakes,
+    //optimistically confirmed slots at or above lowest_root
+    pub confirmed: HashSet<Slot>,
+    //slots confirmed by the last applied block
+    last_confirmed: Vec<Slot>,
+    //votes the towers accepted in the last applied block
+    last_applied_votes: usize,
+    //each node's latest vote over all the banks, empty until the
+    //first full build_fork_weights
+    latest_votes: HashMap<ID, Slot>,
 }
 
 impl Default for Banks {
     fn default() -> Self {
-        let bank_zero = Bank::zero();
+        Self::with_seed(DEFAULT_SEED)
+    }
+}
+
+impl Banks {
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_subcommittee(Subcommittee::with_seed(seed), LOCKOUT_BASE)
+    }
+    pub fn with_subcommittee(subcom: Subcommittee, lockout_base: u64) -> Self {
+        let bank_zero = Bank::genesis(subcom, lockout_base);
+        let lowest_root = bank_zero.lowest_root();
         let mut fork_map = HashMap::new();
         fork_map.insert(0, bank_zero);
         Self {
             fork_map,
+            history: BTreeMap::new(),
+            retain_depth: 0,
             fork_weights: HashMap::new(),
-            lowest_root: Vote::zero(),
+            lowest_root,
+            equivocations: vec![],
+            root_latencies: HashMap::new(),
+            max_children: None,
+            max_children_seen: 0,
+            max_orphan_depth: 0,
+            rooted_chain_length: 1,
+            threshold: THRESHOLD,
+            supermajority: SUPERMAJORITY,
+            stakes: equal_stakes(),
+            //every node has rooted genesis
+            confirmed: [lowest_root.slot].into_iter().collect(),
+            last_confirmed: vec![],
+            last_applied_votes: 0,
+            latest_votes: HashMap::new(),
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Phase {
     SecondaryRotationB,
     PrimaryA2B,
@@ -64,7 +304,20 @@ pub enum Phase {
 
 impl Default for Subcommittee {
     fn default() -> Self {
-        let primary = Self::calc_subcommittee(0);
+        Self::with_seed(DEFAULT_SEED)
+    }
+}
+
+impl Subcommittee {
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_hash(seed, default_hash)
+    }
+    //draws with hash instead of default_hash, see HashFn
+    pub fn with_hash(seed: u64, hash: HashFn) -> Self {
+        Self::new(seed, hash, SUBCOMMITTEE_SIZE)
+    }
+    pub fn new(seed: u64, hash: HashFn, size: usize) -> Self {
+        let primary = Self::calc_subcommittee_with(hash, size, seed, 0);
         let secondary = primary.clone();
         Self {
             parent_super_root: 0,
@@ -73,12 +326,13 @@ impl Default for Subcommittee {
             parent_num_super_roots: 0,
             primary,
             secondary,
+            seed,
+            hash,
+            size,
+            turnover: 0.0,
         }
     }
-}
-
-impl Subcommittee {
-    pub fn child(self: &Self) -> Self {
+    pub fn child(&self) -> Self {
         Self {
             parent_super_root: self.super_root,
             super_root: self.super_root,
@@ -88,39 +342,79 @@ impl Subcommittee {
             parent_num_super_roots: self.num_super_roots,
             primary: self.primary.clone(),
             secondary: self.secondary.clone(),
+            seed: self.seed,
+            hash: self.hash,
+            size: self.size,
+            turnover: self.turnover,
         }
     }
     pub fn init_child(&mut self, parent: &Self) {
         if self.subcommittee_epoch() != parent.subcommittee_epoch() {
             let epoch = self.subcommittee_epoch();
             match self.subcommittee_phase() {
-                Phase::SecondaryRotationB => self.secondary = Self::calc_subcommittee(epoch),
-                Phase::PrimaryA2B => std::mem::swap(&mut self.primary, &mut self.secondary),
-                Phase::SecondaryRotationA => self.secondary = Self::calc_subcommittee(epoch),
-                Phase::PrimaryB2A => std::mem::swap(&mut self.primary, &mut self.secondary),
+                Phase::SecondaryRotationB | Phase::SecondaryRotationA => {
+                    let prev = std::mem::replace(
+                        &mut self.secondary,
+                        Self::calc_subcommittee_with(self.hash, self.size, self.seed, epoch),
+                    );
+                    self.turnover = Self::jaccard_distance(&self.secondary, &prev);
+                }
+                Phase::PrimaryA2B | Phase::PrimaryB2A => {
+                    std::mem::swap(&mut self.primary, &mut self.secondary);
+                    self.turnover = self.turnover(&self.secondary);
+                }
             }
         }
     }
+    //jaccard distance between the primary and a previous draw,
+    //0 when nothing changed and 1 when every member was replaced
+    pub fn turnover(&self, prev: &HashSet<ID>) -> f64 {
+        Self::jaccard_distance(&self.primary, prev)
+    }
+    fn jaccard_distance(a: &HashSet<ID>, b: &HashSet<ID>) -> f64 {
+        let union = a.union(b).count();
+        if union == 0 {
+            return 0.0;
+        }
+        1.0 - a.intersection(b).count() as f64 / union as f64
+    }
 
     pub fn freeze(&mut self, super_root: Slot) {
         self.super_root = super_root;
         if self.super_root != self.parent_super_root {
-            self.num_super_roots = self.num_super_roots + 1;
+            self.num_super_roots += 1;
         }
     }
 
-    fn hash(val: u64) -> u64 {
-        let mut h = DefaultHasher::new();
-        val.hash(&mut h);
-        h.finish()
+    pub fn calc_subcommittee(seed: u64, epoch: usize) -> HashSet<ID> {
+        Self::calc_subcommittee_with(default_hash, SUBCOMMITTEE_SIZE, seed, epoch)
     }
-
-    fn calc_subcommittee(epoch: usize) -> HashSet<ID> {
+    //draws size distinct nodes out of all NUM_NODES. a hash with a short
+    //cycle would never draw enough of them, so after MAX_DRAWS the rest
+    //are drawn with default_hash
+    pub fn calc_subcommittee_with(
+        hash: HashFn,
+        size: usize,
+        seed: u64,
+        epoch: usize,
+    ) -> HashSet<ID> {
+        assert!(
+            size <= NUM_NODES,
+            "subcommittee size {} exceeds {} nodes",
+            size,
+            NUM_NODES
+        );
         let mut set = HashSet::new();
-        let mut seed = Self::hash(epoch as u64);
-        for _ in 0..SUBCOMMITTEE_SIZE {
-            set.insert(seed as usize % SUBCOMMITTEE_SIZE);
-            seed = Self::hash(seed);
+        let mut seed = hash(hash(seed) ^ epoch as u64);
+        let mut draws = 0;
+        while set.len() < size {
+            set.insert(seed as usize % NUM_NODES);
+            draws += 1;
+            seed = if draws < MAX_DRAWS {
+                hash(seed)
+            } else {
+                default_hash(seed ^ draws as u64)
+            };
         }
         set
     }
@@ -140,32 +434,255 @@ impl Subcommittee {
 }
 
 impl Banks {
-    pub fn apply(&mut self, block: &Block) {
-        assert!(self.fork_map.get(&block.slot).is_none());
-        let parent = self.fork_map.get_mut(&block.parent).unwrap();
-        let mut bank = parent.child(block.slot);
+    pub fn apply(&mut self, block: &Block) -> Result<(), ApplyError> {
+        if self.fork_map.contains_key(&block.slot) {
+            return Err(ApplyError::DuplicateSlot(block.slot));
+        }
+        if block.slot <= block.parent {
+            return Err(ApplyError::NotAfterParent {
+                slot: block.slot,
+                parent: block.parent,
+            });
+        }
         let mut fork: HashSet<_> = self.compute_fork(block.parent).into_iter().collect();
-        fork.insert(bank.slot);
-        bank.apply(block, &fork);
+        fork.insert(block.slot);
+        let parent = self
+            .fork_map
+            .get_mut(&block.parent)
+            .ok_or(ApplyError::MissingParent {
+                slot: block.slot,
+                parent: block.parent,
+            })?;
+        if let Some(max) = self.max_children {
+            if parent.children.len() >= max {
+                return Err(ApplyError::TooManyChildren {
+                    parent: parent.slot,
+                    children: parent.children.len(),
+                });
+            }
+        }
+        let mut bank = parent.child(block.slot);
+        self.last_applied_votes = match bank.apply(block, &fork) {
+            Ok(applied) => applied,
+            Err(e) => {
+                parent.children.retain(|c| *c != block.slot);
+                return Err(e);
+            }
+        };
+        self.max_children_seen = self.max_children_seen.max(parent.children.len());
+        for id in &bank.equivocations {
+            self.equivocations.push((bank.slot, *id));
+        }
         let lowest_root = bank.lowest_root();
-        assert!(self.fork_map.get(&bank.slot).is_none());
+        assert!(!self.fork_map.contains_key(&bank.slot));
         let mut max_root = 0;
         for n in bank.nodes.iter() {
             if n.root.slot > max_root {
                 max_root = n.root.slot;
             }
         }
+        let slot = bank.slot;
         self.fork_map.insert(bank.slot, bank);
+        self.last_confirmed = self.calc_newly_confirmed(slot);
+        self.confirmed.extend(&self.last_confirmed);
+        let mut removed = vec![];
+        let mut inherited = 0;
         if lowest_root.slot > self.lowest_root.slot {
-            println!("ROOT DISTANCE {}", max_root - lowest_root.slot);
-            println!(
-                "LOWEST ROOT UPDATE {:?} {:?} MAX: {}",
-                self.lowest_root, lowest_root, max_root
+            log::debug!(
+                "lowest root update slot={} old={:?} new={:?} max={} distance={}",
+                block.slot,
+                self.lowest_root,
+                lowest_root,
+                max_root,
+                max_root - lowest_root.slot
             );
+            //every slot from the old root up to the new one is rooted
+            let rooted: Vec<Slot> = self
+                .compute_fork(lowest_root.slot)
+                .into_iter()
+                .take_while(|s| *s != self.lowest_root.slot)
+                .collect();
+            self.rooted_chain_length += rooted.len();
+            for s in rooted {
+                let latency = (block.slot - s) as usize;
+                self.root_latencies.entry(s).or_insert(latency);
+            }
             self.lowest_root = lowest_root;
-            self.gc();
+            //weight the new root's descendants inherited from the
+            //slots between the old root and the new one
+            let parent = self.fork_map.get(&lowest_root.slot).unwrap().parent;
+            inherited = *self.fork_weights.get(&parent).unwrap_or(&0);
+            removed = self.gc();
+        }
+        self.update_fork_weights(slot, inherited, &removed);
+        Ok(())
+    }
+
+    //slots from genesis to lowest_root along the rooted fork, both
+    //included, what compute_fork(lowest_root) had before gc
+    pub fn rooted_chain_length(&self) -> usize {
+        self.rooted_chain_length
+    }
+
+    //applies a recorded block stream, holding back blocks until their
+    //parent is applied. returns the lowest root after each block
+    pub fn replay(&mut self, blocks: &[Block]) -> Result<Vec<Vote>, ReplayError> {
+        let mut roots = vec![];
+        //blocks waiting on their parent, keyed by the parent slot
+        let mut pending: HashMap<Slot, Vec<&Block>> = HashMap::new();
+        let mut seen = HashSet::new();
+        for block in blocks {
+            if self.fork_map.contains_key(&block.slot) || !seen.insert(block.slot) {
+                return Err(ReplayError::DuplicateSlot(block.slot));
+            }
+            if !self.fork_map.contains_key(&block.parent) {
+                pending.entry(block.parent).or_default().push(block);
+                continue;
+            }
+            let mut ready = vec![block];
+            while let Some(b) = ready.pop() {
+                self.apply(b).map_err(ReplayError::Apply)?;
+                roots.push(self.lowest_root);
+                if let Some(children) = pending.remove(&b.slot) {
+                    ready.extend(children.into_iter().rev());
+                }
+            }
         }
-        self.build_fork_weights();
+        if let Some(b) = pending.values().flatten().min_by_key(|b| b.slot) {
+            return Err(ReplayError::MissingParent {
+                slot: b.slot,
+                parent: b.parent,
+            });
+        }
+        Ok(roots)
+    }
+
+    //slots in the bank's fork that 2/3+ of the nodes have voted on
+    //or past for the first time, the fork up to Bank::confirmation_bound.
+    //the parent's fork was confirmed up to its own bound when it was
+    //applied, so unless the bound moved only the new slot can be new.
+    //otherwise the walk stops at the first confirmed slot
+    fn calc_newly_confirmed(&self, slot: Slot) -> Vec<Slot> {
+        let bank = &self.fork_map[&slot];
+        let bound = bank.confirmation_bound();
+        let parent_bound = self.fork_map[&bank.parent].confirmation_bound();
+        if slot > bound && bound <= parent_bound {
+            return vec![];
+        }
+        let mut newly = vec![];
+        let mut s = slot;
+        while s >= self.lowest_root.slot && !self.confirmed.contains(&s) {
+            if s <= bound {
+                newly.push(s);
+            }
+            let parent = self.fork_map[&s].parent;
+            if parent == s {
+                break;
+            }
+            s = parent;
+        }
+        newly.reverse();
+        newly
+    }
+
+    //slots optimistically confirmed by the last applied block, ascending
+    pub fn newly_confirmed(&self) -> Vec<Slot> {
+        self.last_confirmed.clone()
+    }
+
+    //votes processed by the last applied block
+    pub fn applied_votes(&self) -> usize {
+        self.last_applied_votes
+    }
+
+    //(latency, count) sorted by latency
+    pub fn root_latency_histogram(&self) -> Vec<(usize, usize)> {
+        let mut buckets: HashMap<usize, usize> = HashMap::new();
+        for latency in self.root_latencies.values() {
+            *buckets.entry(*latency).or_insert(0) += 1;
+        }
+        let mut histogram: Vec<_> = buckets.into_iter().collect();
+        histogram.sort_unstable();
+        histogram
+    }
+
+    //nearest rank (p50, p90, p99) of root_latencies, zeros before the
+    //first root
+    pub fn root_latency_percentiles(&self) -> (usize, usize, usize) {
+        let mut latencies: Vec<_> = self.root_latencies.values().copied().collect();
+        if latencies.is_empty() {
+            return (0, 0, 0);
+        }
+        latencies.sort_unstable();
+        let rank = |p: usize| latencies[(p * latencies.len()).div_ceil(100) - 1];
+        (rank(50), rank(90), rank(99))
+    }
+
+    pub fn snapshot(&self) -> BanksSnapshot {
+        let mut fork_map: Vec<_> = self.fork_map.values().map(|b| b.snapshot()).collect();
+        fork_map.sort_by_key(|b| b.slot);
+        let history = self.history.values().map(|b| b.snapshot()).collect();
+        let mut fork_weights: Vec<_> = self.fork_weights.iter().map(|(x, y)| (*x, *y)).collect();
+        fork_weights.sort_unstable();
+        let mut root_latencies: Vec<_> =
+            self.root_latencies.iter().map(|(x, y)| (*x, *y)).collect();
+        root_latencies.sort_unstable();
+        let mut confirmed: Vec<_> = self.confirmed.iter().copied().collect();
+        confirmed.sort_unstable();
+        BanksSnapshot {
+            fork_map,
+            history,
+            fork_weights,
+            lowest_root: self.lowest_root,
+            equivocations: self.equivocations.clone(),
+            root_latencies,
+            max_children_seen: self.max_children_seen,
+            max_orphan_depth: self.max_orphan_depth,
+            rooted_chain_length: self.rooted_chain_length,
+            confirmed,
+        }
+    }
+
+    pub fn from_snapshot(snap: BanksSnapshot, config: &Config) -> Result<Self, SnapshotError> {
+        snap.check()?;
+        let mut fork_map = HashMap::new();
+        for b in snap.fork_map {
+            let mut bank = Bank::from_snapshot(b)?;
+            bank.subcom.hash = config.subcommittee_hash;
+            bank.supermajority = config.supermajority;
+            bank.stakes = config.node_stakes();
+            fork_map.insert(bank.slot, bank);
+        }
+        let mut history = BTreeMap::new();
+        for b in snap.history {
+            let mut bank = Bank::from_snapshot(b)?;
+            bank.subcom.hash = config.subcommittee_hash;
+            bank.supermajority = config.supermajority;
+            bank.stakes = config.node_stakes();
+            history.insert(bank.slot, bank);
+        }
+        let mut banks = Self {
+            fork_map,
+            history,
+            retain_depth: config.retain_depth,
+            fork_weights: HashMap::new(),
+            lowest_root: snap.lowest_root,
+            equivocations: snap.equivocations,
+            root_latencies: snap.root_latencies.into_iter().collect(),
+            max_children: config.max_children,
+            max_children_seen: snap.max_children_seen,
+            max_orphan_depth: snap.max_orphan_depth,
+            rooted_chain_length: snap.rooted_chain_length,
+            threshold: config.threshold,
+            supermajority: config.supermajority,
+            stakes: config.node_stakes(),
+            confirmed: snap.confirmed.into_iter().collect(),
+            last_confirmed: vec![],
+            last_applied_votes: 0,
+            latest_votes: HashMap::new(),
+        };
+        banks.build_fork_weights();
+        Ok(banks)
     }
 
     pub fn compute_fork(&self, slot: Slot) -> Vec<Slot> {
@@ -184,14 +701,247 @@ impl Banks {
         fork
     }
 
-    //only keep forks that are connected to root
-    fn gc(&mut self) {
+    //every root to leaf fork, each starting at lowest_root
+    pub fn forks(&self) -> Vec<Vec<Slot>> {
+        let mut forks = vec![];
+        let mut children = vec![self.lowest_root.slot];
+        while let Some(slot) = children.pop() {
+            let bank = self.fork_map.get(&slot).unwrap();
+            if !bank.children.is_empty() {
+                children.extend_from_slice(&bank.children);
+                continue;
+            }
+            let mut fork = self.compute_fork(slot);
+            //drop the gc'd ancestors of lowest_root
+            let root = fork
+                .iter()
+                .position(|x| *x == self.lowest_root.slot)
+                .unwrap();
+            fork.truncate(root + 1);
+            fork.reverse();
+            forks.push(fork);
+        }
+        forks.sort();
+        forks
+    }
+
+    //the bank at slot, whether it's still in fork_map or was pruned
+    //within retain_depth slots of lowest_root
+    pub fn historical_bank(&self, slot: Slot) -> Option<&Bank> {
+        self.fork_map.get(&slot).or_else(|| self.history.get(&slot))
+    }
+
+    //the bank at slot in fork_map
+    pub fn bank(&self, slot: Slot) -> Option<&Bank> {
+        self.fork_map.get(&slot)
+    }
+
+    //None if the slot is not in fork_map
+    pub fn is_frozen(&self, slot: Slot) -> Option<bool> {
+        self.bank(slot).map(|b| b.frozen)
+    }
+
+    //banks still being built, ascending. apply freezes each bank before
+    //inserting it, so this is empty unless a bank was added some other way
+    pub fn unfrozen_banks(&self) -> Vec<Slot> {
+        let mut slots: Vec<_> = self
+            .fork_map
+            .values()
+            .filter(|b| !b.frozen)
+            .map(|b| b.slot)
+            .collect();
+        slots.sort_unstable();
+        slots
+    }
+
+    //(heaviest slot, its weight, weight of the heaviest slot on a
+    //competing fork), picked with HeaviestFork like the nodes' votes so
+    //ties go to the higher slot. slots that are an ancestor or a
+    //descendant of the heaviest slot don't compete
+    pub fn heaviest_margin(&self) -> (Slot, usize, usize) {
+        let heaviest = HeaviestFork.choose(&self.fork_weights, self);
+        let weight = *self.fork_weights.get(&heaviest).unwrap_or(&0);
+        let runner_up = self.runner_up(heaviest).map(|(_, w)| w).unwrap_or(0);
+        (heaviest, weight, runner_up)
+    }
+
+    //heaviest slot that is neither an ancestor nor a descendant of
+    //heaviest, highest slot on ties
+    fn runner_up(&self, heaviest: Slot) -> Option<(Slot, usize)> {
+        let fork = self.compute_fork(heaviest);
+        self.fork_weights
+            .iter()
+            .filter(|(s, _)| !fork.contains(s) && !self.compute_fork(**s).contains(&heaviest))
+            .map(|(s, w)| (*s, *w))
+            .max_by_key(|(s, w)| (*w, *s))
+    }
+
+    //slot of the first bank at which the two heaviest competing forks
+    //each have 1/3+ of the stake rooted past their common ancestor.
+    //a rooted node can't switch, so from then on neither fork can get
+    //the 2/3+ it needs to root. None if either side is short
+    pub fn partition_deadlock_slot(&self) -> Option<Slot> {
+        let heaviest = HeaviestFork.choose(&self.fork_weights, self);
+        let (runner_up, _) = self.runner_up(heaviest)?;
+        let split = self.common_ancestor(heaviest, runner_up)?;
+        //first slot of each side after the split
+        let side = |slot: Slot| {
+            let fork = self.compute_fork(slot);
+            let ix = fork.iter().position(|s| *s == split).unwrap();
+            fork[ix - 1]
+        };
+        let certain = |start: Slot| {
+            self.fork_map
+                .values()
+                .filter(|b| {
+                    let rooted: usize = (0..NUM_NODES)
+                        .filter(|id| b.nodes[*id].root.slot > split)
+                        .map(|id| b.stake(id))
+                        .sum();
+                    rooted > minority_stake(b.total_stake(), self.supermajority)
+                        && self.compute_fork(b.slot).contains(&start)
+                })
+                .map(|b| b.slot)
+                .min()
+        };
+        let a = certain(side(heaviest))?;
+        let b = certain(side(runner_up))?;
+        Some(a.max(b))
+    }
+
+    //banks in the fork_map, gc prunes all but lowest_root and its
+    //descendants
+    pub fn len(&self) -> usize {
+        self.fork_map.len()
+    }
+
+    //never true, the fork_map always has the lowest root
+    pub fn is_empty(&self) -> bool {
+        self.fork_map.is_empty()
+    }
+
+    //slots in the fork_map, ascending
+    pub fn active_slots(&self) -> Vec<Slot> {
+        let mut slots: Vec<Slot> = self.fork_map.keys().copied().collect();
+        slots.sort();
+        slots
+    }
+
+    //number of leaf banks reachable from lowest_root, same as
+    //forks().len() without building the forks
+    pub fn fork_count(&self) -> usize {
+        let mut count = 0;
+        let mut children = vec![self.lowest_root.slot];
+        while let Some(slot) = children.pop() {
+            let bank = self.fork_map.get(&slot).unwrap();
+            if bank.children.is_empty() {
+                count += 1;
+            }
+            children.extend_from_slice(&bank.children);
+        }
+        count
+    }
+
+    //highest slot on both forks, None if either slot is not in the fork_map
+    pub fn common_ancestor(&self, a: Slot, b: Slot) -> Option<Slot> {
+        if !self.fork_map.contains_key(&a) || !self.fork_map.contains_key(&b) {
+            return None;
+        }
+        let fork_a: HashSet<_> = self.compute_fork(a).into_iter().collect();
+        self.compute_fork(b)
+            .into_iter()
+            .find(|x| fork_a.contains(x))
+            .or(Some(self.lowest_root.slot))
+    }
+
+    //graphviz digraph of the forks from lowest_root, slots that a node
+    //has rooted are filled and lowest_root is red
+    pub fn to_dot(&self) -> String {
+        fn escape(label: &str) -> String {
+            label.replace('\\', "\\\\").replace('"', "\\\"")
+        }
+        let mut rooted = HashSet::new();
+        for b in self.fork_map.values() {
+            rooted.extend(b.nodes.iter().map(|n| n.root.slot));
+        }
+        let mut dot = String::from("digraph forks {\n");
+        let mut children = vec![self.lowest_root.slot];
+        while let Some(slot) = children.pop() {
+            let bank = self.fork_map.get(&slot).unwrap();
+            //escape each line, then join them with a dot newline
+            let label = [
+                slot.to_string(),
+                format!("weight {}", self.fork_weights.get(&slot).unwrap_or(&0)),
+            ]
+            .iter()
+            .map(|x| escape(x))
+            .collect::<Vec<_>>()
+            .join("\\n");
+            let style = if slot == self.lowest_root.slot {
+                ", style=filled, fillcolor=red"
+            } else if rooted.contains(&slot) {
+                ", style=filled, fillcolor=lightblue"
+            } else {
+                ""
+            };
+            dot += &format!("    \"{}\" [label=\"{}\"{}];\n", slot, label, style);
+            for child in &bank.children {
+                dot += &format!("    \"{}\" -> \"{}\";\n", slot, child);
+            }
+            children.extend_from_slice(&bank.children);
+        }
+        dot += "}\n";
+        dot
+    }
+
+    //every bank's parent is in fork_map and lists it as a child, except
+    //for the root, and every child is in fork_map
+    pub fn verify_tree(&self) -> Result<(), String> {
+        let root = self.lowest_root.slot;
+        if !self.fork_map.contains_key(&root) {
+            return Err(format!("root {} is missing", root));
+        }
+        for (slot, bank) in &self.fork_map {
+            //child requires a frozen parent
+            if !bank.frozen && !bank.children.is_empty() {
+                return Err(format!("{} has children but isn't frozen", slot));
+            }
+            for child in &bank.children {
+                match self.fork_map.get(child) {
+                    None => return Err(format!("child {} of {} is missing", child, slot)),
+                    Some(c) if c.parent != *slot => {
+                        return Err(format!(
+                            "child {} of {} has parent {}",
+                            child, slot, c.parent
+                        ))
+                    }
+                    _ => (),
+                }
+            }
+            if *slot == root {
+                continue;
+            }
+            match self.fork_map.get(&bank.parent) {
+                None => return Err(format!("parent {} of {} is missing", bank.parent, slot)),
+                Some(p) if !p.children.contains(slot) => {
+                    return Err(format!(
+                        "parent {} doesn't list child {}",
+                        bank.parent, slot
+                    ))
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    //only keep forks that are connected to root, returns the removed slots
+    fn gc(&mut self) -> Vec<Slot> {
         let mut valid = vec![];
 
-        println!("START GC {:?}", self.lowest_root);
+        log::debug!("start gc root={:?}", self.lowest_root);
         let mut children = vec![self.lowest_root.slot];
-        while !children.is_empty() {
-            let slot = children.pop().unwrap();
+        while let Some(slot) = children.pop() {
             valid.push(slot);
             let bank = self.fork_map.get(&slot).unwrap();
             children.extend_from_slice(&bank.children);
@@ -200,12 +950,56 @@ impl Banks {
         for v in valid {
             new_banks.insert(v, self.fork_map.remove(&v).unwrap());
         }
-        self.fork_map = new_banks;
+        let removed = self.fork_map.keys().copied().collect();
+        let pruned = std::mem::replace(&mut self.fork_map, new_banks);
+        let root = self.lowest_root.slot;
+        self.max_orphan_depth = self.max_orphan_depth.max(self.orphan_depth(&pruned));
+        self.confirmed.retain(|x| *x >= root);
+        let oldest = root.saturating_sub(self.retain_depth as Slot);
+        if self.retain_depth > 0 {
+            self.history
+                .extend(pruned.into_iter().filter(|(slot, _)| *slot >= oldest));
+        }
+        self.history = self.history.split_off(&oldest);
+        removed
+    }
+    //longest branch in the pruned banks that is not an ancestor of
+    //lowest_root, counted from where it left the rooted chain
+    fn orphan_depth(&self, pruned: &HashMap<Slot, Bank>) -> usize {
+        let mut rooted = HashSet::new();
+        let mut slot = self.fork_map[&self.lowest_root.slot].parent;
+        while let Some(bank) = pruned.get(&slot) {
+            if !rooted.insert(slot) {
+                break;
+            }
+            slot = bank.parent;
+        }
+        pruned
+            .keys()
+            .filter(|s| !rooted.contains(s))
+            .map(|s| {
+                let mut depth = 0;
+                let mut slot = *s;
+                while pruned.contains_key(&slot) && !rooted.contains(&slot) {
+                    depth += 1;
+                    slot = pruned[&slot].parent;
+                }
+                depth
+            })
+            .max()
+            .unwrap_or(0)
+    }
+    pub fn stake(&self, id: ID) -> usize {
+        self.stakes[id] as usize
+    }
+    //upper bound for every fork weight
+    pub fn total_stake(&self) -> usize {
+        self.stakes.iter().sum::<u64>() as usize
     }
     /// A validator V's vote on an ancestor X counts towards a descendant
     /// Y even if the validator is not locked out on X at Y anymore,
     /// as long as X is the latest vote observed from this validator V
-    pub fn build_fork_weights(&mut self) {
+    fn calc_fork_weights(&self) -> (HashMap<ID, Slot>, HashMap<Slot, usize>) {
         //each validators latest votes
         let mut latest_votes: HashMap<ID, Slot> = HashMap::new();
         for v in self.fork_map.values() {
@@ -213,39 +1007,148 @@ impl Banks {
         }
         //total stake voting per slot
         let mut slot_votes: HashMap<Slot, usize> = HashMap::new();
-        for (_, v) in &latest_votes {
-            let e = slot_votes.entry(*v).or_insert(0);
-            *e = *e + 1;
+        for (id, v) in &latest_votes {
+            *slot_votes.entry(*v).or_insert(0) += self.stake(*id);
         }
         //stake weight is inherited from the parent
         let mut weights: HashMap<Slot, usize> = HashMap::new();
         let mut children = vec![self.lowest_root.slot];
-        while !children.is_empty() {
-            let child = children.pop().unwrap();
+        while let Some(child) = children.pop() {
             let bank = self.fork_map.get(&child).unwrap();
             children.extend_from_slice(&bank.children);
             let parent_weight = *weights.get(&bank.parent).unwrap_or(&0);
-            let e = weights.entry(child).or_insert(parent_weight);
-            *e = *e + *slot_votes.get(&child).unwrap_or(&0);
+            *weights.entry(child).or_insert(parent_weight) += *slot_votes.get(&child).unwrap_or(&0);
         }
+        (latest_votes, weights)
+    }
+    //walks every bank and every fork, apply only updates the weights
+    //that a new bank changes
+    pub fn build_fork_weights(&mut self) {
+        let (latest_votes, weights) = self.calc_fork_weights();
+        self.latest_votes = latest_votes;
         self.fork_weights = weights;
     }
+    //the weights build_fork_weights would compute, to check the
+    //incremental updates against in the tests
+    #[cfg(test)]
+    pub(crate) fn rebuilt_fork_weights(&self) -> HashMap<Slot, usize> {
+        self.calc_fork_weights().1
+    }
+    //same result as build_fork_weights after applying the bank at slot,
+    //given the slots the gc removed and the weight the surviving slots
+    //inherited from the removed ancestors of the new root
+    fn update_fork_weights(&mut self, slot: Slot, inherited: usize, removed: &[Slot]) {
+        if self.latest_votes.is_empty() {
+            self.build_fork_weights();
+            return;
+        }
+        let removed: HashSet<Slot> = removed.iter().copied().collect();
+        for s in &removed {
+            self.fork_weights.remove(s);
+        }
+        if !removed.is_empty() {
+            for w in self.fork_weights.values_mut() {
+                *w -= inherited;
+            }
+        }
+        let parent = self.fork_map.get(&slot).unwrap().parent;
+        let parent_weight = *self.fork_weights.get(&parent).unwrap_or(&0);
+        self.fork_weights.insert(slot, parent_weight);
+        //change in the stake of the latest votes on each slot
+        let mut deltas: HashMap<Slot, isize> = HashMap::new();
+        //nodes whose latest vote was gc'd fall back to the latest one
+        //in the remaining banks
+        let stale: Vec<ID> = self
+            .latest_votes
+            .iter()
+            .filter(|(_, s)| removed.contains(s))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in stale {
+            let latest = self
+                .fork_map
+                .values()
+                .map(|b| b.nodes[id].latest_vote().unwrap_or(&b.nodes[id].root).slot)
+                .max()
+                .unwrap();
+            self.latest_votes.insert(id, latest);
+            *deltas.entry(latest).or_insert(0) += self.stake(id) as isize;
+        }
+        let bank = self.fork_map.get(&slot).unwrap();
+        for (id, n) in bank.nodes.iter().enumerate() {
+            let latest = n.latest_vote().unwrap_or(&n.root).slot;
+            let old = self.latest_votes.get_mut(&id).unwrap();
+            if latest > *old {
+                let stake = self.stakes[id] as isize;
+                *deltas.entry(*old).or_insert(0) -= stake;
+                *deltas.entry(latest).or_insert(0) += stake;
+                *old = latest;
+            }
+        }
+        //a vote on a slot counts towards every descendant
+        for (s, delta) in deltas {
+            if delta == 0 || !self.fork_map.contains_key(&s) {
+                continue;
+            }
+            let mut children = vec![s];
+            while let Some(child) = children.pop() {
+                let w = self.fork_weights.get_mut(&child).unwrap();
+                *w = (*w as isize + delta) as usize;
+                children.extend_from_slice(&self.fork_map.get(&child).unwrap().children);
+            }
+        }
+    }
 }
 
 impl Bank {
-    pub fn zero() -> Self {
+    pub fn zero(seed: u64) -> Self {
+        Self::genesis(Subcommittee::with_seed(seed), LOCKOUT_BASE)
+    }
+    pub fn genesis(subcom: Subcommittee, lockout_base: u64) -> Self {
         let mut nodes = vec![];
         for _ in 0..NUM_NODES {
-            nodes.push(Tower::default());
+            nodes.push(Tower::with_base(lockout_base));
         }
         Bank {
             frozen: true,
-            nodes,
+            nodes: Arc::new(nodes),
             slot: 0,
             parent: 0,
-            subcom: Subcommittee::default(),
+            subcom,
             children: vec![],
+            equivocations: vec![],
+            supermajority: SUPERMAJORITY,
+            stakes: equal_stakes(),
+        }
+    }
+    pub fn snapshot(&self) -> BankSnapshot {
+        BankSnapshot {
+            slot: self.slot,
+            parent: self.parent,
+            children: self.children.clone(),
+            frozen: self.frozen,
+            nodes: self.nodes.to_vec(),
+            subcom: self.subcom.clone(),
+            equivocations: self.equivocations.clone(),
+        }
+    }
+    pub fn from_snapshot(snap: BankSnapshot) -> Result<Self, SnapshotError> {
+        if snap.nodes.len() != NUM_NODES {
+            return Err(SnapshotError {
+                message: format!("bank {} has {} towers", snap.slot, snap.nodes.len()),
+            });
         }
+        Ok(Bank {
+            nodes: Arc::new(snap.nodes),
+            slot: snap.slot,
+            parent: snap.parent,
+            frozen: snap.frozen,
+            children: snap.children,
+            subcom: snap.subcom,
+            equivocations: snap.equivocations,
+            supermajority: SUPERMAJORITY,
+            stakes: equal_stakes(),
+        })
     }
     pub fn child(&mut self, slot: Slot) -> Self {
         assert!(self.frozen);
@@ -256,48 +1159,145 @@ impl Bank {
             children: vec![],
             subcom: self.subcom.child(),
             frozen: false,
+            equivocations: vec![],
+            supermajority: self.supermajority,
+            stakes: self.stakes.clone(),
         };
         b.subcom.init_child(&self.subcom);
         self.children.push(slot);
         b
     }
-    pub fn apply(&mut self, block: &Block, fork: &HashSet<Slot>) {
+    //returns the number of votes the towers accepted. the bank is unchanged if a
+    //vote is not in the fork, unless it's from a node that equivocated
+    //in this block. each node gets at most one vote list applied
+    pub fn apply(&mut self, block: &Block, fork: &HashSet<Slot>) -> Result<usize, ApplyError> {
         assert!(!self.frozen);
         assert_eq!(self.slot, block.slot);
         assert_eq!(self.parent, block.parent);
         for (id, votes) in &block.votes {
+            if *id >= self.nodes.len() {
+                return Err(ApplyError::UnknownNode(*id));
+            }
+            let base = self.nodes[*id].base;
+            if let Some(v) = votes.iter().find(|v| v.lockout != base) {
+                return Err(ApplyError::InvalidLockout { id: *id, vote: *v });
+            }
+        }
+        //the same node voted on two different slots in this block, or its
+        //latest vote is below one the bank already has from it. checked
+        //before the fork, the conflicting votes are usually off it. an
+        //empty vote list, e.g. from a node that restarted, conflicts
+        //with nothing
+        let mut latest: HashMap<ID, Slot> = HashMap::new();
+        let mut equivocators: HashSet<ID> = HashSet::new();
+        for (id, votes) in &block.votes {
+            let slot = match votes.last() {
+                Some(v) => v.slot,
+                None => continue,
+            };
+            let prev = *latest.entry(*id).or_insert(slot);
+            let regressed = self.nodes[*id]
+                .latest_vote()
+                .map(|v| v.slot > slot)
+                .unwrap_or(false);
+            if prev != slot || regressed {
+                equivocators.insert(*id);
+            }
+        }
+        for (id, _) in &block.votes {
+            if equivocators.contains(id) && !self.equivocations.contains(id) {
+                self.equivocations.push(*id);
+            }
+        }
+        let in_fork = |votes: &[Vote]| votes.iter().all(|v| fork.contains(&v.slot));
+        for (id, votes) in &block.votes {
+            if equivocators.contains(id) {
+                continue;
+            }
+            if let Some(v) = votes.iter().find(|v| !fork.contains(&v.slot)) {
+                return Err(ApplyError::VoteNotInFork {
+                    id: *id,
+                    slot: v.slot,
+                });
+            }
+        }
+        let mut applied = 0;
+        //an equivocator's first vote list on the fork is applied, so the
+        //tower doesn't depend on the order the leader included them in.
+        //the others are only evidence
+        let mut voted: HashSet<ID> = HashSet::new();
+        for (id, votes) in block.votes.iter().filter(|(_, votes)| in_fork(votes)) {
+            if !voted.insert(*id) {
+                continue;
+            }
+            //towers are resent every block, votes the tower already has
+            //are rejected and not counted
             for v in votes {
-                assert!(
-                    fork.contains(&v.slot),
-                    "proposed vote is not in the bank's fork {:?} {}",
-                    fork,
-                    v.slot
-                );
-                let _e = self.nodes[*id].apply(v);
+                if Arc::make_mut(&mut self.nodes)[*id].apply(v).is_ok() {
+                    applied += 1;
+                }
             }
         }
         let super_root = self.calc_super_root().slot;
         self.subcom.freeze(super_root);
         self.frozen = true;
+        Ok(applied)
+    }
+    //applies votes to one node's tower without a block, for tests of the
+    //tower and threshold logic. the votes are only checked against the
+    //fork if one is given. returns the number of votes applied, the bank
+    //is unchanged on error. unlike apply it doesn't freeze the bank or
+    //record equivocations
+    pub fn apply_votes(
+        &mut self,
+        id: ID,
+        votes: &[Vote],
+        fork: Option<&HashSet<Slot>>,
+    ) -> Result<usize, ApplyError> {
+        if id >= self.nodes.len() {
+            return Err(ApplyError::UnknownNode(id));
+        }
+        let base = self.nodes[id].base;
+        if let Some(v) = votes.iter().find(|v| v.lockout != base) {
+            return Err(ApplyError::InvalidLockout { id, vote: *v });
+        }
+        if let Some(v) = fork.and_then(|f| votes.iter().find(|v| !f.contains(&v.slot))) {
+            return Err(ApplyError::VoteNotInFork { id, slot: v.slot });
+        }
+        let mut tower = self.nodes[id].clone();
+        for v in votes {
+            tower.apply(v).map_err(|error| ApplyError::Tower {
+                id,
+                vote: *v,
+                error,
+            })?;
+        }
+        Arc::make_mut(&mut self.nodes)[id] = tower;
+        Ok(votes.len())
+    }
+    //nodes whose root is at or past slot
+    pub fn rooted_count(&self, slot: Slot) -> usize {
+        self.nodes.iter().filter(|n| n.root.slot >= slot).count()
     }
-    pub fn calc_threshold_slot(&self, mult: u64, vote: &Vote) -> usize {
+    //stake of the nodes locked out on the vote's slot
+    pub fn calc_threshold_slot(&self, threshold: usize, mult: u64, vote: &Vote) -> usize {
+        let base = self.lockout_base();
         let count: usize = self
             .nodes
             .iter()
-            .map(|n| {
+            .enumerate()
+            .map(|(id, n)| {
                 //alredy rooted
                 if n.root.slot >= vote.slot {
-                    return 1;
+                    return self.stake(id);
                 }
                 for v in &n.votes {
-                    if vote.lockout == 1 << THRESHOLD && v.slot >= vote.slot {
-                        return 1;
+                    if vote.lockout == base.pow(threshold as u32) && v.slot >= vote.slot {
+                        return self.stake(id);
                     }
                     //check if the node has a higher vote with at least 1/2 the lockout
-                    if v.slot >= vote.slot
-                        && (v.slot + (mult * v.lockout)) >= (vote.slot + vote.lockout)
-                    {
-                        return 1;
+                    if v.slot >= vote.slot && (v.slot + (mult * v.lockout)) >= vote.expiry() {
+                        return self.stake(id);
                     }
                 }
                 0
@@ -305,19 +1305,108 @@ impl Bank {
             .sum();
         count
     }
-    pub fn threshold_slot(&self, vote: &Vote) -> bool {
-        self.calc_threshold_slot(1 << THRESHOLD, vote) > (2 * NUM_NODES) / 3
+    pub fn stake(&self, id: ID) -> usize {
+        self.stakes[id] as usize
+    }
+    pub fn total_stake(&self) -> usize {
+        self.stakes.iter().sum::<u64>() as usize
+    }
+    //highest of the nodes' values that more than a supermajority of the
+    //stake is at or above, none if the ratio leaves no stake
+    fn supermajority_value<T: Ord + Copy>(&self, values: Vec<T>) -> Option<T> {
+        let mut values: Vec<(T, usize)> = values
+            .into_iter()
+            .enumerate()
+            .map(|(id, v)| (v, self.stake(id)))
+            .collect();
+        values.sort_unstable_by_key(|v| std::cmp::Reverse(v.0));
+        let needed = supermajority_stake(self.total_stake(), self.supermajority);
+        let mut stake = 0;
+        for (v, s) in values {
+            stake += s;
+            if stake > needed {
+                return Some(v);
+            }
+        }
+        None
+    }
+    //highest slot that more than a supermajority of the stake has
+    //rooted or voted at or past, on any fork. threshold_slot passes for
+    //a vote of lockout base^threshold on any slot up to it
+    pub fn confirmation_bound(&self) -> Slot {
+        let latest: Vec<Slot> = self
+            .nodes
+            .iter()
+            .map(|n| n.votes.iter().map(|v| v.slot).fold(n.root.slot, Slot::max))
+            .collect();
+        self.supermajority_value(latest).unwrap_or(0)
+    }
+    pub fn threshold_slot(&self, threshold: usize, vote: &Vote) -> bool {
+        let mult = self.lockout_base().pow(threshold as u32);
+        self.calc_threshold_slot(threshold, mult, vote)
+            > supermajority_stake(self.total_stake(), self.supermajority)
+    }
+    //every tower in a bank is built with the same base
+    pub fn lockout_base(&self) -> u64 {
+        self.nodes[0].base
+    }
+    //(primary, secondary) subcommittees
+    pub fn subcommittee_members(&self) -> (&HashSet<ID>, &HashSet<ID>) {
+        (&self.subcom.primary, &self.subcom.secondary)
+    }
+    //rotation phase of the subcommittee epoch this bank is in
+    pub fn current_phase(&self) -> Phase {
+        self.subcom.subcommittee_phase()
+    }
+    //nodes with a vote in their tower that is not in the fork and still
+    //locks them out at this bank's slot
+    pub fn locked_out_nodes(&self, heaviest_fork: &[Slot]) -> Vec<ID> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| {
+                n.votes
+                    .iter()
+                    .any(|v| v.expiry() >= self.slot && !heaviest_fork.contains(&v.slot))
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+    //(slot, expiry) for each vote in the node's tower, latest first,
+    //the node can't vote on a fork without slot until after expiry
+    pub fn lockout_slots(&self, id: ID) -> Vec<(Slot, Slot)> {
+        self.nodes[id]
+            .votes
+            .iter()
+            .map(|v| (v.slot, v.expiry()))
+            .collect()
+    }
+    //largest k such that 2/3+ of the stake has a lockout of at least
+    //base^k on the slot, rooted nodes count as DEPTH
+    pub fn confirmation_depth(&self, slot: Slot) -> u32 {
+        let base = self.lockout_base();
+        let depths: Vec<u32> = self
+            .nodes
+            .iter()
+            .map(|n| {
+                if n.root.slot >= slot {
+                    return DEPTH as u32;
+                }
+                n.lockout_at(slot).map(|l| l.ilog(base)).unwrap_or(0)
+            })
+            .collect();
+        self.supermajority_value(depths).unwrap_or(0)
     }
     pub fn calc_super_root(&self) -> Vote {
-        let mut roots: Vec<_> = self.nodes.iter().map(|n| n.root).collect();
-        roots.sort_by_key(|x| x.slot);
-        //2/3 of the nodes are at least at this root
-        roots[NUM_NODES / 3]
+        let roots: Vec<_> = self.nodes.iter().map(|n| n.root).collect();
+        //a supermajority of the stake is at least at this root
+        self.supermajority_value(roots)
+            .unwrap_or_else(|| self.lowest_root())
     }
 
     fn lowest_root(&self) -> Vote {
         let mut roots: Vec<_> = self.nodes.iter().map(|n| n.root).collect();
-        roots.sort_by_key(|x| x.slot);
+        roots.sort();
         roots[0]
     }
 
@@ -332,3 +1421,493 @@ impl Bank {
         }
     }
 }
+
+#[test]
+fn test_calc_subcommittee_seed() {
+    let a = Subcommittee::calc_subcommittee(1, 3);
+    assert_eq!(a, Subcommittee::calc_subcommittee(1, 3));
+    assert_ne!(a, Subcommittee::calc_subcommittee(2, 3));
+    assert_ne!(a, Subcommittee::calc_subcommittee(1, 4));
+    //splitmix64 draws don't depend on the toolchain
+    assert_eq!(splitmix64(0), 0xe220_a839_7b1d_cdaf);
+    let b = Subcommittee::with_hash(1, splitmix64);
+    assert_eq!(
+        b.primary,
+        Subcommittee::calc_subcommittee_with(splitmix64, SUBCOMMITTEE_SIZE, 1, 0)
+    );
+    assert_ne!(b.primary, Subcommittee::calc_subcommittee(1, 0));
+}
+
+#[test]
+fn test_calc_subcommittee_short_cycle() {
+    fn xorshift(mut x: u64) -> u64 {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^ (x << 17)
+    }
+    //xorshift(0) == 0, so seed 0 only ever draws node 0
+    assert_eq!(xorshift(0), 0);
+    let subcom = Subcommittee::with_hash(0, xorshift);
+    assert_eq!(subcom.primary.len(), SUBCOMMITTEE_SIZE);
+    assert!(subcom.primary.contains(&0));
+    let constant = Subcommittee::calc_subcommittee_with(|_| 7, NUM_NODES, 0, 0);
+    assert_eq!(constant.len(), NUM_NODES);
+}
+
+#[test]
+fn test_calc_subcommittee_size() {
+    let a = Subcommittee::calc_subcommittee(1, 3);
+    assert_eq!(a.len(), SUBCOMMITTEE_SIZE);
+    assert!(a.iter().all(|id| *id < NUM_NODES));
+    assert!(a.iter().any(|id| *id >= SUBCOMMITTEE_SIZE));
+    let all = Subcommittee::calc_subcommittee_with(default_hash, NUM_NODES, 1, 3);
+    assert!(all.contains(&(NUM_NODES - 1)));
+    assert_eq!(all, (0..NUM_NODES).collect());
+}
+
+#[test]
+fn test_confirmation_depth() {
+    let mut bank = Bank::zero(DEFAULT_SEED);
+    assert_eq!(bank.confirmation_depth(1), 0);
+    for n in Arc::make_mut(&mut bank.nodes)
+        .iter_mut()
+        .take((2 * NUM_NODES) / 3 + 1)
+    {
+        let _ = n.apply(&Vote::new(1));
+        let _ = n.apply(&Vote::new(2));
+    }
+    assert_eq!(bank.confirmation_depth(1), 2);
+    assert_eq!(bank.confirmation_depth(2), 1);
+    assert_eq!(bank.confirmation_depth(3), 0);
+    Arc::make_mut(&mut bank.nodes)[0] = Tower::default();
+    assert_eq!(bank.confirmation_depth(1), 0);
+    assert_eq!(bank.rooted_count(0), NUM_NODES);
+    assert_eq!(bank.rooted_count(1), 0);
+    Arc::make_mut(&mut bank.nodes)[1].root = Vote::new(2);
+    assert_eq!(bank.rooted_count(1), 1);
+}
+
+#[test]
+fn test_newly_confirmed() {
+    let mut banks = Banks::default();
+    let voters = supermajority_nodes(SUPERMAJORITY) + 1;
+    let block = |slot, parent, votes: &[Slot]| {
+        let mut b = BlockBuilder::default().slot(slot).parent(parent);
+        for id in 0..voters {
+            for v in votes {
+                b = b.vote(id, *v);
+            }
+        }
+        b.build()
+    };
+    banks.apply(&block(1, 0, &[])).unwrap();
+    assert!(banks.newly_confirmed().is_empty());
+    banks.apply(&block(2, 1, &[1])).unwrap();
+    assert_eq!(banks.newly_confirmed(), vec![1]);
+    //the bound didn't move, so the fork isn't walked
+    banks.apply(&block(3, 1, &[])).unwrap();
+    assert!(banks.newly_confirmed().is_empty());
+    banks.apply(&block(4, 2, &[])).unwrap();
+    assert!(banks.newly_confirmed().is_empty());
+    //confirms 2 and 4, 1 is already confirmed
+    banks.apply(&block(5, 4, &[2, 4])).unwrap();
+    assert_eq!(banks.newly_confirmed(), vec![2, 4]);
+}
+
+#[test]
+fn test_fork_queries() {
+    let mut banks = Banks::default();
+    assert_eq!(banks.fork_count(), 1);
+    for (slot, parent) in [(1, 0), (2, 1), (3, 1), (4, 3)] {
+        banks
+            .apply(&Block {
+                slot,
+                parent,
+                votes: vec![],
+            })
+            .unwrap();
+    }
+    assert_eq!(banks.common_ancestor(2, 4), Some(1));
+    assert_eq!(banks.common_ancestor(3, 4), Some(3));
+    assert_eq!(banks.common_ancestor(0, 4), Some(0));
+    assert_eq!(banks.common_ancestor(2, 5), None);
+    assert_eq!(banks.forks(), vec![vec![0, 1, 2], vec![0, 1, 3, 4]]);
+    assert_eq!(banks.fork_count(), 2);
+    assert_eq!(banks.verify_tree(), Ok(()));
+    banks.fork_map.get_mut(&1).unwrap().children.push(6);
+    assert_eq!(
+        banks.verify_tree(),
+        Err("child 6 of 1 is missing".to_string())
+    );
+    banks.fork_map.get_mut(&1).unwrap().children.pop();
+    assert_eq!(banks.max_children_seen, 2);
+    banks.max_children = Some(2);
+    let block = Block {
+        slot: 5,
+        parent: 1,
+        votes: vec![],
+    };
+    assert_eq!(
+        banks.apply(&block),
+        Err(ApplyError::TooManyChildren {
+            parent: 1,
+            children: 2
+        })
+    );
+}
+
+#[test]
+fn test_active_slots() {
+    let mut banks = Banks::default();
+    assert_eq!(banks.len(), 1);
+    assert!(!banks.is_empty());
+    for (slot, parent) in [(3, 0), (1, 0), (2, 1)] {
+        banks
+            .apply(&Block {
+                slot,
+                parent,
+                votes: vec![],
+            })
+            .unwrap();
+    }
+    assert_eq!(banks.len(), 4);
+    assert_eq!(banks.active_slots(), vec![0, 1, 2, 3]);
+}
+
+#[test]
+fn test_replay_order() {
+    let block = |slot, parent| Block {
+        slot,
+        parent,
+        votes: vec![],
+    };
+    //children arriving before their parent are held back
+    let mut banks = Banks::default();
+    let roots = banks.replay(&[block(2, 1), block(1, 0)]).unwrap();
+    assert_eq!(roots.len(), 2);
+    assert_eq!(banks.fork_map[&2].parent, 1);
+    let mut banks = Banks::default();
+    assert_eq!(
+        banks.replay(&[block(1, 0), block(1, 0)]),
+        Err(ReplayError::DuplicateSlot(1))
+    );
+    let mut banks = Banks::default();
+    assert_eq!(
+        banks.replay(&[block(3, 2)]),
+        Err(ReplayError::MissingParent { slot: 3, parent: 2 })
+    );
+}
+
+#[test]
+fn test_lockout_slots() {
+    let mut bank = Bank::zero(DEFAULT_SEED);
+    assert_eq!(bank.lockout_slots(0), vec![]);
+    for slot in 1..4 {
+        let _ = Arc::make_mut(&mut bank.nodes)[0].apply(&Vote::new(slot));
+    }
+    assert_eq!(bank.lockout_slots(0), vec![(3, 5), (2, 6), (1, 9)]);
+    assert_eq!(bank.lockout_slots(1), vec![]);
+}
+
+#[test]
+fn test_locked_out_nodes() {
+    let mut bank = Bank::zero(DEFAULT_SEED);
+    let _ = Arc::make_mut(&mut bank.nodes)[0].apply(&Vote::new(1));
+    let _ = Arc::make_mut(&mut bank.nodes)[1].apply(&Vote::new(2));
+    let _ = Arc::make_mut(&mut bank.nodes)[2].apply(&Vote::new(3));
+    let fork = [3, 0];
+    bank.slot = 3;
+    assert_eq!(bank.locked_out_nodes(&fork), vec![0, 1]);
+    //node 0's vote on 1 expired at 3
+    bank.slot = 4;
+    assert_eq!(bank.locked_out_nodes(&fork), vec![1]);
+    bank.slot = 5;
+    assert!(bank.locked_out_nodes(&fork).is_empty());
+}
+
+#[test]
+fn test_block_builder() {
+    let block = BlockBuilder::default()
+        .slot(3)
+        .parent(1)
+        .vote(0, 1)
+        .vote(1, 1)
+        .vote_with_lockout(0, 2, 4)
+        .build();
+    assert_eq!(
+        block,
+        Block {
+            slot: 3,
+            parent: 1,
+            votes: vec![
+                (
+                    0,
+                    vec![
+                        Vote::new(1),
+                        Vote {
+                            slot: 2,
+                            lockout: 4
+                        }
+                    ]
+                ),
+                (1, vec![Vote::new(1)]),
+            ],
+        }
+    );
+}
+
+#[test]
+fn test_heaviest_margin() {
+    let mut banks = Banks::default();
+    let block = |slot, parent| BlockBuilder::default().slot(slot).parent(parent);
+    banks.apply(&block(1, 0).build()).unwrap();
+    //1 inherits the weight of 0, HeaviestFork picks the higher slot
+    assert_eq!(banks.heaviest_margin(), (1, NUM_NODES, 0));
+    banks.apply(&block(2, 0).build()).unwrap();
+    banks
+        .apply(&block(3, 1).vote(0, 1).vote(1, 1).build())
+        .unwrap();
+    banks.apply(&block(4, 2).vote(2, 2).build()).unwrap();
+    //3 descends from 1 with the same weight, 2 and 4 compete with it
+    assert_eq!(banks.heaviest_margin(), (3, NUM_NODES - 1, NUM_NODES - 2));
+}
+
+#[test]
+fn test_subcommittee_rotation() {
+    let mut bank = Bank::zero(DEFAULT_SEED);
+    let mut phases = vec![bank.current_phase()];
+    let (primary, _) = bank.subcommittee_members();
+    let first = primary.clone();
+    let mut secondary = None;
+    let mut rotations = vec![];
+    //every bank roots its parent, so each one is a new super root
+    for slot in 1..=(SUBCOMMITTEE_EPOCH * 4 + 2) as Slot {
+        let mut child = bank.child(slot);
+        for n in Arc::make_mut(&mut child.nodes).iter_mut() {
+            n.root.slot = slot - 1;
+        }
+        let block = BlockBuilder::default().slot(slot).parent(slot - 1).build();
+        child.apply(&block, &HashSet::new()).unwrap();
+        if phases.last() != Some(&child.current_phase()) {
+            phases.push(child.current_phase());
+            rotations.push(child.subcom.turnover);
+            if child.current_phase() == Phase::SecondaryRotationA {
+                secondary = Some(child.subcommittee_members().1.clone());
+            }
+        }
+        bank = child;
+    }
+    assert_eq!(
+        phases,
+        vec![
+            Phase::SecondaryRotationB,
+            Phase::PrimaryA2B,
+            Phase::SecondaryRotationA,
+            Phase::PrimaryB2A,
+            Phase::SecondaryRotationB,
+        ]
+    );
+    //the secondary drawn in SecondaryRotationA is swapped in as the primary
+    assert_ne!(bank.subcommittee_members().0, &first);
+    assert_eq!(Some(bank.subcommittee_members().0), secondary.as_ref());
+    //the genesis secondary is a copy of the primary, so the first swap
+    //keeps every member, later swaps and draws replace most of them
+    assert_eq!(rotations[0], 0.0);
+    assert!(rotations[1..].iter().all(|t| *t > 0.5), "{rotations:?}");
+}
+
+#[test]
+fn test_subcommittee_turnover() {
+    let a = Subcommittee::calc_subcommittee(1, 0);
+    let mut subcom = Subcommittee::with_seed(1);
+    assert_eq!(subcom.turnover(&a), 0.0);
+    subcom.primary = Subcommittee::calc_subcommittee(1, 1);
+    let turnover = subcom.turnover(&a);
+    //independent draws of a small subcommittee barely overlap
+    assert!(turnover > 0.5 && turnover <= 1.0, "{turnover}");
+    subcom.primary = HashSet::new();
+    assert_eq!(subcom.turnover(&a), 1.0);
+}
+
+#[test]
+fn test_partition_deadlock_slot() {
+    let mut banks = Banks::default();
+    let block = |slot, parent| BlockBuilder::default().slot(slot).parent(parent);
+    banks.apply(&block(1, 0).build()).unwrap();
+    banks.apply(&block(2, 0).build()).unwrap();
+    banks.apply(&block(3, 1).vote(0, 1).build()).unwrap();
+    banks
+        .apply(&block(4, 2).vote(1, 2).vote(2, 2).build())
+        .unwrap();
+    assert_eq!(banks.partition_deadlock_slot(), None);
+    //1/3+ of the nodes rooted on slot 1's side
+    let nodes = Arc::make_mut(&mut banks.fork_map.get_mut(&3).unwrap().nodes);
+    for n in nodes.iter_mut().take(NUM_NODES / 3 + 1) {
+        n.root.slot = 1;
+    }
+    assert_eq!(banks.partition_deadlock_slot(), None);
+    //and 1/3+ rooted on slot 2's side by slot 5
+    banks.apply(&block(5, 4).vote(3, 4).build()).unwrap();
+    let nodes = Arc::make_mut(&mut banks.fork_map.get_mut(&5).unwrap().nodes);
+    for n in nodes.iter_mut().skip(NUM_NODES / 2) {
+        n.root.slot = 2;
+    }
+    assert_eq!(banks.partition_deadlock_slot(), Some(5));
+    //a single fork is never deadlocked
+    let mut banks = Banks::default();
+    banks.apply(&block(1, 0).vote(0, 0).build()).unwrap();
+    assert_eq!(banks.partition_deadlock_slot(), None);
+}
+
+#[test]
+fn test_frozen_banks() {
+    let mut banks = Banks::default();
+    let block = |slot, parent| BlockBuilder::default().slot(slot).parent(parent);
+    banks.apply(&block(1, 0).build()).unwrap();
+    assert_eq!(banks.bank(1).map(|b| b.parent), Some(0));
+    assert!(banks.bank(2).is_none());
+    assert_eq!(banks.is_frozen(1), Some(true));
+    assert_eq!(banks.is_frozen(2), None);
+    assert!(banks.unfrozen_banks().is_empty());
+    //a bank being built outside of apply
+    let bank = banks.fork_map.get_mut(&1).unwrap().child(2);
+    banks.fork_map.insert(2, bank);
+    assert_eq!(banks.unfrozen_banks(), vec![2]);
+    assert!(banks.verify_tree().is_ok());
+    //a child was made before the parent was frozen
+    banks.fork_map.get_mut(&2).unwrap().children.push(3);
+    assert_eq!(
+        banks.verify_tree(),
+        Err("2 has children but isn't frozen".to_string())
+    );
+}
+
+#[test]
+fn test_apply_errors() {
+    let mut banks = Banks::default();
+    let block = |slot, parent| BlockBuilder::default().slot(slot).parent(parent);
+    banks.apply(&block(1, 0).build()).unwrap();
+    banks.apply(&block(2, 0).build()).unwrap();
+    assert_eq!(
+        banks.apply(&block(1, 0).build()),
+        Err(ApplyError::DuplicateSlot(1))
+    );
+    assert_eq!(
+        banks.apply(&block(4, 3).build()),
+        Err(ApplyError::MissingParent { slot: 4, parent: 3 })
+    );
+    //node 7's vote on 2 is on the other fork
+    assert_eq!(
+        banks.apply(&block(3, 1).vote(0, 1).vote(7, 2).build()),
+        Err(ApplyError::VoteNotInFork { id: 7, slot: 2 })
+    );
+    //the rejected block left no trace
+    assert!(!banks.fork_map.contains_key(&3));
+    assert!(banks.fork_map[&1].children.is_empty());
+    banks.verify_tree().unwrap();
+    banks.apply(&block(3, 1).vote(0, 1).build()).unwrap();
+    //an equivocator's tower off the fork is recorded, not applied
+    let equivocation = Block {
+        slot: 4,
+        parent: 3,
+        votes: vec![(7, vec![Vote::new(1)]), (7, vec![Vote::new(2)])],
+    };
+    banks.apply(&equivocation).unwrap();
+    assert_eq!(banks.equivocations, vec![(4, 7)]);
+    assert_eq!(
+        banks.fork_map[&4].nodes[7].latest_vote(),
+        Some(&Vote::new(1))
+    );
+    //an empty vote list from a node the bank has a vote from isn't an
+    //equivocation, and node 7 is only exempt from the fork check in 4
+    let restarted = Block {
+        slot: 5,
+        parent: 4,
+        votes: vec![(0, vec![]), (7, vec![Vote::new(2)])],
+    };
+    assert_eq!(
+        banks.apply(&restarted),
+        Err(ApplyError::VoteNotInFork { id: 7, slot: 2 })
+    );
+    let restarted = Block {
+        slot: 5,
+        parent: 4,
+        votes: vec![(0, vec![])],
+    };
+    banks.apply(&restarted).unwrap();
+    assert_eq!(banks.equivocations, vec![(4, 7)]);
+    //both of node 9's lists are on the fork, only the first is applied
+    let equivocation = Block {
+        slot: 6,
+        parent: 5,
+        votes: vec![(9, vec![Vote::new(3)]), (9, vec![Vote::new(1)])],
+    };
+    banks.apply(&equivocation).unwrap();
+    assert_eq!(banks.equivocations, vec![(4, 7), (6, 9)]);
+    assert_eq!(
+        banks.fork_map[&6].nodes[9].latest_vote(),
+        Some(&Vote::new(3))
+    );
+}
+
+#[test]
+fn test_apply_votes() {
+    let mut bank = Bank::zero(DEFAULT_SEED);
+    let votes: Vec<_> = (1..4).map(Vote::new).collect();
+    assert_eq!(bank.apply_votes(0, &votes, None), Ok(3));
+    assert_eq!(bank.nodes[0].lockout_at(1), Some(8));
+    assert_eq!(bank.nodes[0].lockout_at(3), Some(2));
+    //one node alone can't pass the threshold
+    assert!(!bank.threshold_slot(
+        1,
+        &Vote {
+            slot: 2,
+            lockout: 4
+        }
+    ));
+    //a fork is only enforced when given
+    let fork: HashSet<Slot> = [0, 1, 2, 3].into_iter().collect();
+    assert_eq!(
+        bank.apply_votes(1, &[Vote::new(2), Vote::new(5)], Some(&fork)),
+        Err(ApplyError::VoteNotInFork { id: 1, slot: 5 })
+    );
+    assert_eq!(bank.nodes[1].latest_vote(), None);
+    assert_eq!(bank.apply_votes(1, &votes, Some(&fork)), Ok(3));
+    assert_eq!(bank.nodes[1].votes(), bank.nodes[0].votes());
+    //the same checks as apply, and the tower's own errors come through
+    assert_eq!(
+        bank.apply_votes(NUM_NODES, &votes, None),
+        Err(ApplyError::UnknownNode(NUM_NODES))
+    );
+    let vote = Vote {
+        slot: 4,
+        lockout: 4,
+    };
+    assert_eq!(
+        bank.apply_votes(2, &[vote], None),
+        Err(ApplyError::InvalidLockout { id: 2, vote })
+    );
+    assert_eq!(
+        bank.apply_votes(0, &[Vote::new(4), Vote::new(3)], None),
+        Err(ApplyError::Tower {
+            id: 0,
+            vote: Vote::new(3),
+            error: TowerError::NonMonotonic,
+        })
+    );
+    //nothing from the failed call was applied
+    assert_eq!(bank.nodes[0].latest_vote(), Some(&Vote::new(3)));
+}
+
+#[test]
+fn test_root_latency_percentiles() {
+    let mut banks = Banks::default();
+    assert_eq!(banks.root_latency_percentiles(), (0, 0, 0));
+    //roots 1..=100, slot s took s slots to root
+    banks.root_latencies = (1..=100).map(|s| (s as Slot, s)).collect();
+    assert_eq!(banks.root_latency_percentiles(), (50, 90, 99));
+    banks.root_latencies = [(1, 7)].into_iter().collect();
+    assert_eq!(banks.root_latency_percentiles(), (7, 7, 7));
+    banks.root_latencies = [(1, 3), (2, 1), (3, 2)].into_iter().collect();
+    assert_eq!(banks.root_latency_percentiles(), (2, 3, 3));
+}
@@ -0,0 +1,207 @@
+use crate::bank::{
+    default_hash, equal_stakes, HashFn, Stakes, DEFAULT_SEED, NUM_NODES, SUBCOMMITTEE_SIZE,
+    SUPERMAJORITY,
+};
+use crate::inclusion_policy::InclusionPolicy;
+use crate::leader_schedule::LeaderSchedule;
+use crate::node::THRESHOLD;
+use crate::snapshot::hash_name;
+use crate::tower::{Slot, DEPTH, LOCKOUT_BASE};
+use std::sync::Arc;
+
+/// Parameters a `Network` is constructed with.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Config {
+    //seed for the subcommittee draws
+    pub seed: u64,
+    //lockouts of base^threshold and above need 2/3+ of the stake
+    //to be locked out on the slot before a node increases them
+    pub threshold: usize,
+    //blocks on a parent with this many children are rejected
+    pub max_children: Option<usize>,
+    //which node produces the block for each slot
+    pub leader_schedule: LeaderSchedule,
+    //hash for the subcommittee draws. it shouldn't have short cycles,
+    //see HashFn. snapshots save it by name and only restore default_hash
+    //and splitmix64
+    #[serde(
+        serialize_with = "crate::snapshot::serialize_hash",
+        deserialize_with = "crate::snapshot::deserialize_hash"
+    )]
+    pub subcommittee_hash: HashFn,
+    //nodes in each subcommittee draw
+    pub subcommittee_size: usize,
+    //factor tower lockouts grow by, 2 doubles them
+    pub lockout_base: u64,
+    //pruned banks up to this many slots below lowest_root are kept
+    //for Banks::historical_bank
+    pub retain_depth: usize,
+    //chance of a slot getting no block, drawn from a hash of the seed
+    //and the slot
+    pub skip_rate: f64,
+    //slots that never get a block
+    pub skip_slots: Vec<Slot>,
+    //chance of a slot's leader failing to produce its block, drawn from
+    //a hash of the seed, the slot and the leader
+    pub leader_failure_rate: f64,
+    //fraction of the nodes that vote each step, drawn from a hash of
+    //the seed, the slot and the node
+    pub participation: f64,
+    //(numerator, denominator) of the fraction of the stake that is a
+    //supermajority, the rest is the 1/3 a switching proof needs at 2/3
+    pub supermajority: (u64, u64),
+    //stake of each node by id, fork weights and the supermajority
+    //checks add it up. empty gives every node a stake of 1
+    pub stakes: Vec<u64>,
+    //which votes leaders put in their blocks
+    pub inclusion_policy: InclusionPolicy,
+    //nodes vote on the ancestor this many blocks behind the heaviest
+    //slot, modeling replay lag. 0 votes on the tip
+    pub vote_lag: usize,
+}
+
+//function pointers don't compare reliably, so the hashes are compared
+//by the name a snapshot saves them under
+impl PartialEq for Config {
+    fn eq(&self, other: &Self) -> bool {
+        let Config {
+            seed,
+            threshold,
+            max_children,
+            leader_schedule,
+            subcommittee_hash,
+            subcommittee_size,
+            lockout_base,
+            retain_depth,
+            skip_rate,
+            skip_slots,
+            leader_failure_rate,
+            participation,
+            supermajority,
+            stakes,
+            inclusion_policy,
+            vote_lag,
+        } = self;
+        *seed == other.seed
+            && *threshold == other.threshold
+            && *max_children == other.max_children
+            && *leader_schedule == other.leader_schedule
+            && hash_name(*subcommittee_hash) == hash_name(other.subcommittee_hash)
+            && *subcommittee_size == other.subcommittee_size
+            && *lockout_base == other.lockout_base
+            && *retain_depth == other.retain_depth
+            && *skip_rate == other.skip_rate
+            && *skip_slots == other.skip_slots
+            && *leader_failure_rate == other.leader_failure_rate
+            && *participation == other.participation
+            && *supermajority == other.supermajority
+            && *stakes == other.stakes
+            && *inclusion_policy == other.inclusion_policy
+            && *vote_lag == other.vote_lag
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            seed: DEFAULT_SEED,
+            threshold: THRESHOLD,
+            max_children: None,
+            leader_schedule: LeaderSchedule::default(),
+            subcommittee_hash: default_hash,
+            subcommittee_size: SUBCOMMITTEE_SIZE,
+            lockout_base: LOCKOUT_BASE,
+            retain_depth: 0,
+            skip_rate: 0.0,
+            skip_slots: vec![],
+            leader_failure_rate: 0.0,
+            inclusion_policy: InclusionPolicy::default(),
+            participation: 1.0,
+            supermajority: SUPERMAJORITY,
+            stakes: vec![],
+            vote_lag: 0,
+        }
+    }
+}
+
+impl Config {
+    pub fn validate(&self) {
+        if let Err(message) = self.check() {
+            panic!("{}", message);
+        }
+    }
+    //validate without panicking, for configs read from a file
+    pub fn check(&self) -> Result<(), String> {
+        //a tower roots its oldest vote once it reaches base^DEPTH, so a
+        //threshold at or above DEPTH would never be checked
+        if self.threshold == 0 || self.threshold >= DEPTH {
+            return Err(format!(
+                "threshold {} must be in 1..{}",
+                self.threshold, DEPTH
+            ));
+        }
+        if self.max_children == Some(0) {
+            return Err("max_children must allow a child".to_string());
+        }
+        if self.subcommittee_size == 0 || self.subcommittee_size > NUM_NODES {
+            return Err(format!(
+                "subcommittee_size {} must be in 1..={}",
+                self.subcommittee_size, NUM_NODES
+            ));
+        }
+        //the threshold check multiplies a lockout of up to base^DEPTH
+        //by base^threshold
+        if self.lockout_base < 2
+            || self
+                .lockout_base
+                .checked_pow((self.threshold + DEPTH) as u32)
+                .is_none()
+        {
+            return Err(format!(
+                "lockout_base {} must be at least 2 and base^(threshold + {}) must fit in a u64",
+                self.lockout_base, DEPTH
+            ));
+        }
+        //every slot skipped would never produce a block
+        if !(0.0..1.0).contains(&self.skip_rate) {
+            return Err(format!("skip_rate {} must be in 0.0..1.0", self.skip_rate));
+        }
+        if !(self.participation > 0.0 && self.participation <= 1.0) {
+            return Err(format!(
+                "participation {} must be in (0.0, 1.0]",
+                self.participation
+            ));
+        }
+        //at half or below two disjoint groups could both be one
+        let (num, den) = self.supermajority;
+        if !(num < den && 2 * num > den) {
+            return Err(format!("supermajority {}/{} must be in (1/2, 1)", num, den));
+        }
+        //fork weights add up the stakes, so the total has to fit
+        if !self.stakes.is_empty() {
+            let total = (self.stakes.iter())
+                .try_fold(0u64, |total, s| total.checked_add(*s))
+                .unwrap_or(0);
+            if self.stakes.len() != NUM_NODES || total == 0 {
+                return Err(format!(
+                    "stakes must be empty or {} stakes with a nonzero total that fits in a u64",
+                    NUM_NODES
+                ));
+            }
+        }
+        if !(0.0..1.0).contains(&self.leader_failure_rate) {
+            return Err(format!(
+                "leader_failure_rate {} must be in 0.0..1.0",
+                self.leader_failure_rate
+            ));
+        }
+        self.leader_schedule.check()
+    }
+    //the stakes banks are built with
+    pub fn node_stakes(&self) -> Stakes {
+        if self.stakes.is_empty() {
+            return equal_stakes();
+        }
+        Arc::new(self.stakes.clone())
+    }
+}
@@ -0,0 +1,109 @@
+use crate::bank::Banks;
+use crate::tower::Slot;
+use std::collections::HashMap;
+
+/// Picks the slot a node votes on from the fork weights visible to it.
+/// The chosen slot must be in `banks` and descend from the lowest root,
+/// other slots are rejected with `RejectReason::ForkChoice`.
+pub trait ForkChoice {
+    fn choose(&self, weights: &HashMap<Slot, usize>, banks: &Banks) -> Slot;
+    //saved in snapshots, see from_name. rules from outside this module
+    //are "custom" and their snapshots can't be loaded
+    fn name(&self) -> &'static str {
+        "custom"
+    }
+}
+
+//the rule a snapshot named, None for custom rules
+pub fn from_name(name: &str) -> Option<Box<dyn ForkChoice>> {
+    match name {
+        "heaviest_fork" => Some(Box::new(HeaviestFork)),
+        "lowest_slot_tie_break" => Some(Box::new(LowestSlotTieBreak)),
+        "latency_aware" => Some(Box::new(LatencyAware)),
+        _ => None,
+    }
+}
+
+//the max weight slot, ties go to the highest slot
+#[derive(Default)]
+pub struct HeaviestFork;
+
+impl ForkChoice for HeaviestFork {
+    fn choose(&self, weights: &HashMap<Slot, usize>, _banks: &Banks) -> Slot {
+        weights
+            .iter()
+            .map(|(x, y)| (y, x))
+            .max()
+            .map(|(_, y)| *y)
+            .unwrap_or(0)
+    }
+    fn name(&self) -> &'static str {
+        "heaviest_fork"
+    }
+}
+
+//the max weight slot, ties go to the lowest slot
+#[derive(Default)]
+pub struct LowestSlotTieBreak;
+
+impl ForkChoice for LowestSlotTieBreak {
+    fn choose(&self, weights: &HashMap<Slot, usize>, _banks: &Banks) -> Slot {
+        weights
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+            .map(|(x, _)| *x)
+            .unwrap_or(0)
+    }
+    fn name(&self) -> &'static str {
+        "lowest_slot_tie_break"
+    }
+}
+
+//the max weight slot after taking off 1% of the total stake for each
+//slot it is behind the newest visible one, so a fork whose blocks
+//stopped arriving loses to a slightly lighter one that is still
+//growing. ties go to the highest slot
+#[derive(Default)]
+pub struct LatencyAware;
+
+impl ForkChoice for LatencyAware {
+    fn choose(&self, weights: &HashMap<Slot, usize>, banks: &Banks) -> Slot {
+        let penalty = banks.total_stake() / 100;
+        let newest = weights.keys().max().copied().unwrap_or(0);
+        weights
+            .iter()
+            .map(|(slot, weight)| {
+                let age = (newest - slot) as usize;
+                (weight.saturating_sub(age * penalty), *slot)
+            })
+            .max()
+            .map(|(_, slot)| slot)
+            .unwrap_or(0)
+    }
+    fn name(&self) -> &'static str {
+        "latency_aware"
+    }
+}
+
+#[test]
+fn test_fork_choice() {
+    let banks = Banks::default();
+    let choose = |rule: &dyn ForkChoice, weights: &[(Slot, usize)]| {
+        rule.choose(&weights.iter().copied().collect(), &banks)
+    };
+    let tie = [(1, 3), (2, 5), (3, 5)];
+    assert_eq!(choose(&HeaviestFork, &tie), 3);
+    assert_eq!(choose(&LowestSlotTieBreak, &tie), 2);
+    assert_eq!(choose(&LatencyAware, &tie), 3);
+    //2 stopped growing 2 slots ago, 4 is lighter but current
+    let stale = [(1, 10), (2, 500), (4, 490)];
+    assert_eq!(choose(&HeaviestFork, &stale), 2);
+    assert_eq!(choose(&LowestSlotTieBreak, &stale), 2);
+    assert_eq!(choose(&LatencyAware, &stale), 4);
+    //a much heavier fork still wins
+    let heavy = [(1, 10), (2, 600), (4, 490)];
+    assert_eq!(choose(&LatencyAware, &heavy), 2);
+    for rule in ["heaviest_fork", "lowest_slot_tie_break", "latency_aware"] {
+        assert_eq!(from_name(rule).unwrap().name(), rule);
+    }
+}
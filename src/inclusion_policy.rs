@@ -0,0 +1,85 @@
+use crate::bank::ID;
+use crate::tower::{Slot, Vote};
+use std::cmp::Reverse;
+use std::collections::HashSet;
+
+/// Which of the votes on its fork a leader puts in its block, see
+/// `Node::make_block_with_policy`.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InclusionPolicy {
+    //every vote
+    #[default]
+    All,
+    //at most this many towers, in the order they arrived
+    Cap(usize),
+    //at most this many towers, subcommittee members first
+    SubcommitteeFirst(usize),
+    //drops towers whose latest vote is more than this many slots old
+    Window(Slot),
+    //at most cap towers, subcommittee members first, then the highest
+    //fee by id, arrival order breaks ties. ids past the end of fees pay
+    //0, pass StakeDistribution::stakes to prioritize by stake
+    Priority {
+        cap: usize,
+        fees: Vec<u64>,
+    },
+}
+
+impl InclusionPolicy {
+    //the towers the leader of slot includes, subcommittee is the
+    //primary subcommittee of the block's parent
+    pub fn select(
+        &self,
+        slot: Slot,
+        mut votes: Vec<(ID, Vec<Vote>)>,
+        subcommittee: &HashSet<ID>,
+    ) -> Vec<(ID, Vec<Vote>)> {
+        match self {
+            InclusionPolicy::All => (),
+            InclusionPolicy::Cap(cap) => votes.truncate(*cap),
+            InclusionPolicy::SubcommitteeFirst(cap) => {
+                //stable, so each group keeps its arrival order
+                votes.sort_by_key(|(id, _)| !subcommittee.contains(id));
+                votes.truncate(*cap);
+            }
+            InclusionPolicy::Window(window) => votes.retain(|(_, votes)| {
+                votes
+                    .last()
+                    .is_some_and(|v| v.slot.saturating_add(*window) >= slot)
+            }),
+            InclusionPolicy::Priority { cap, fees } => {
+                votes.sort_by_key(|(id, _)| {
+                    let fee = fees.get(*id).copied().unwrap_or(0);
+                    (!subcommittee.contains(id), Reverse(fee))
+                });
+                votes.truncate(*cap);
+            }
+        }
+        votes
+    }
+}
+
+#[test]
+fn test_select() {
+    let tower = |id: ID, slot: Slot| (id, vec![Vote::new(slot)]);
+    let votes = vec![tower(0, 9), tower(1, 5), tower(2, 8), tower(3, 2)];
+    let subcommittee: HashSet<ID> = [2, 3].into_iter().collect();
+    let ids = |policy: InclusionPolicy| -> Vec<ID> {
+        policy
+            .select(10, votes.clone(), &subcommittee)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect()
+    };
+    assert_eq!(ids(InclusionPolicy::All), [0, 1, 2, 3]);
+    assert_eq!(ids(InclusionPolicy::Cap(2)), [0, 1]);
+    assert!(ids(InclusionPolicy::Cap(0)).is_empty());
+    assert_eq!(ids(InclusionPolicy::SubcommitteeFirst(3)), [2, 3, 0]);
+    assert_eq!(ids(InclusionPolicy::Window(2)), [0, 2]);
+    assert_eq!(ids(InclusionPolicy::Window(8)), [0, 1, 2, 3]);
+    let priority = |cap: usize, fees: Vec<u64>| ids(InclusionPolicy::Priority { cap, fees });
+    assert_eq!(priority(4, vec![1, 5]), [2, 3, 1, 0]);
+    assert_eq!(priority(4, vec![0, 0, 1, 2]), [3, 2, 0, 1]);
+    assert_eq!(priority(3, vec![]), [2, 3, 0]);
+}
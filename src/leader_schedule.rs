@@ -0,0 +1,57 @@
+use crate::bank::{splitmix64, ID, NUM_NODES};
+use crate::tower::Slot;
+
+/// Maps each slot to the node that produces its block.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaderSchedule {
+    //a pseudo random node per slot, drawn with splitmix64 so seeded runs
+    //give the same leaders on every rust version
+    Hashed,
+    //slot % NUM_NODES
+    #[default]
+    RoundRobin,
+    //cycle through these nodes, a single node gets every slot
+    Fixed(Vec<ID>),
+}
+
+impl LeaderSchedule {
+    pub fn leader(&self, slot: Slot) -> ID {
+        match self {
+            LeaderSchedule::Hashed => splitmix64(slot) as usize % NUM_NODES,
+            LeaderSchedule::RoundRobin => slot as usize % NUM_NODES,
+            LeaderSchedule::Fixed(ids) => ids[slot as usize % ids.len()],
+        }
+    }
+
+    //the scheduled leader if eligible, otherwise the next eligible node
+    //after it, by id for Hashed and RoundRobin or in the Fixed list
+    pub fn leader_among(&self, slot: Slot, eligible: impl Fn(ID) -> bool) -> Option<ID> {
+        match self {
+            LeaderSchedule::Fixed(ids) => (0..ids.len())
+                .map(|k| ids[(slot as usize + k) % ids.len()])
+                .find(|id| eligible(*id)),
+            _ => {
+                let start = self.leader(slot);
+                (0..NUM_NODES)
+                    .map(|k| (start + k) % NUM_NODES)
+                    .find(|id| eligible(*id))
+            }
+        }
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        if let LeaderSchedule::Fixed(ids) = self {
+            if ids.is_empty() {
+                return Err("fixed leader schedule is empty".to_string());
+            }
+            if ids.iter().any(|id| *id >= NUM_NODES) {
+                return Err(format!(
+                    "fixed leader schedule has an unknown node {:?}",
+                    ids
+                ));
+            }
+        }
+        Ok(())
+    }
+}
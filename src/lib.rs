@@ -1,4 +1,12 @@
 pub mod bank;
+pub mod config;
+pub mod fork_choice;
+pub mod inclusion_policy;
+pub mod leader_schedule;
 pub mod network;
 pub mod node;
+pub mod scenario;
+pub mod slashing;
+pub mod snapshot;
+pub mod stake;
 pub mod tower;
@@ -1,7 +1,21 @@
 use tower_sim::network;
+use tower_sim::scenario::Scenario;
 
 fn main() {
+    env_logger::init();
     let mut network = network::Network::default();
+    //nothing here undoes a step, don't copy the network before each one
+    network.set_undo_depth(0);
+    if let Some(path) = std::env::args().nth(1) {
+        let scenario = Scenario::load(&path).unwrap_or_else(|e| {
+            panic!("{}:{}: {}", path, e.line, e.message);
+        });
+        if let Err(e) = network.run_scenario(&scenario) {
+            panic!("{}:{}: {}", path, e.line, e.message);
+        }
+        println!("root {:?}", network.root());
+        return;
+    }
     let mut num_partitions = 1;
     const TIME: usize = 256;
     let mut partition_slot = 0;
@@ -16,7 +30,7 @@ fn main() {
         }
         if num_partitions > 1 && partition_slot + TIME / 8 <= slot && slot % (TIME / 8) == 0 {
             println!("REPAIRING PARTITIONS=================================");
-            num_partitions = num_partitions - 1;
+            num_partitions -= 1;
             network.repair_partitions(num_partitions);
         }
     }
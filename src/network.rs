@@ -1,91 +1,2555 @@
+use crate::bank::ApplyError;
 use crate::bank::Banks;
+use crate::bank::Block;
+use crate::bank::Subcommittee;
 use crate::bank::ID;
 use crate::bank::NUM_NODES;
+use crate::bank::{minority_stake, splitmix64, supermajority_stake};
+use crate::config::Config;
+use crate::fork_choice::{ForkChoice, HeaviestFork};
+use crate::inclusion_policy::InclusionPolicy;
+use crate::leader_schedule::LeaderSchedule;
 use crate::node::Node;
+use crate::node::{CatchUpError, RejectReason, SwitchStats, VoteRejection};
+use crate::scenario::{Command, Scenario, ScenarioError};
+use crate::snapshot::{NetworkSnapshot, SnapshotError, StepEvent};
+use crate::stake::{StakeDistribution, StakeError};
 use crate::tower::Slot;
 use crate::tower::Vote;
+use crate::tower::DEPTH;
+use crate::tower::{Tower, TowerError};
 //use rayon::prelude::*;
-use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
 use std::collections::VecDeque;
-use std::hash::{Hash, Hasher};
+use std::fs;
+use std::io::Write;
+
+//changes a produced block before it's applied, see set_block_mutator
+pub type BlockMutator = Box<dyn FnMut(&mut Block)>;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum InvariantViolation {
+    //both slots are supermajority roots but neither descends from the other
+    ConflictingRoots {
+        a: Slot,
+        b: Slot,
+    },
+    //a node's root is behind the lowest root
+    NodeBelowLowestRoot {
+        id: ID,
+        root: Slot,
+        lowest_root: Slot,
+    },
+    //more weight on a slot than there are nodes
+    ForkWeightExceedsStake {
+        slot: Slot,
+        weight: usize,
+    },
+}
 
 pub struct Network {
+    config: Config,
     nodes: Vec<Node>,
     banks: Banks,
     slot: Slot,
     num_partitions: usize,
+    //partition of each node for weighted partitions, empty when
+    //nodes are split by id modulo num_partitions
+    partition_map: Vec<usize>,
+    //reachability[p][q] is true when partition q sees partition p's
+    //blocks and votes, empty when each partition only sees itself
+    reachability: Vec<Vec<bool>>,
     partitioned_blocks: VecDeque<(ID, Slot)>,
+    //slots before a block is visible to nodes other than its producer
+    delay_slots: usize,
+    //(producer, block) keyed by the slot they are delivered at
+    in_flight: BTreeMap<Slot, Vec<(ID, Slot)>>,
+    //votes rejected since the last drain_rejections
+    rejections: Vec<VoteRejection>,
+    //nodes that failed the switching proof on the last step
+    blocked_switches: usize,
+    //votes applied by the last step's block, 0 when the slot was skipped
+    last_step_votes: usize,
+    //votes applied over every step
+    total_votes: usize,
+    //applied blocks since start_recording, None when not recording
+    recording: Option<Vec<Block>>,
+    //the latest applied block, kept through skipped slots
+    last_block: Option<Block>,
+    //nodes reconnected to every partition by a gradual repair
+    healed: Vec<bool>,
+    //fraction of the nodes healed on each step, 0 when not healing
+    heal_rate: f64,
+    //number of nodes healed so far, including the partial one
+    heal_progress: f64,
+    //called with the new lowest root each time it advances
+    root_callbacks: Vec<Box<dyn FnMut(Vote)>>,
+    //runs on each produced block before it's applied
+    block_mutator: Option<BlockMutator>,
+    //blocks the banks rejected, by the slot they were produced in
+    apply_errors: Vec<(Slot, ApplyError)>,
+    //the network before each of the last undo_depth steps and the
+    //length of rejections then, oldest first. see undo_state
+    undo_history: VecDeque<(Network, usize)>,
+    undo_depth: usize,
+    //gets a json line per step, see StepEvent
+    event_stream: Option<Box<dyn Write>>,
+    //slot of the last repair_partitions, and the first slot after it
+    //that the nodes were converged at
+    repaired_at: Option<Slot>,
+    converged_at: Option<Slot>,
+    //tip of the network's heaviest fork after the last step
+    heaviest_tip: Option<Slot>,
+    //(slot, (old tip, new tip)) for each step that switched to a fork
+    //that doesn't descend from the old tip
+    reorgs: Vec<(Slot, (Slot, Slot))>,
+}
+
+/// The dominant reason the network isn't rooting, see `root_stall_reason`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StallReason {
+    //the heaviest fork has the latest votes of at most 2/3 of the stake
+    NoSupermajority { weight: usize },
+    //nodes with 1/3+ of the stake have tower votes that are not in the
+    //heaviest fork
+    LockedOut { nodes: usize },
+    //most of the nodes failed the switching proof on the last step
+    SwitchBlocked { nodes: usize },
+    //none of the above
+    Other,
+}
+
+/// How far `other` has diverged from `self`, see `Network::diff`.
+/// Differences are other's value minus self's.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RunDiff {
+    pub root: i64,
+    pub fork_count: i64,
+    //nodes whose own root differs between the runs
+    pub differing_roots: usize,
+    //largest gap between a node's root in the two runs
+    pub max_root_gap: Slot,
+}
+
+/// What happened over the slots advanced by `Network::step_n`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct StepSummary {
+    //steps that moved the lowest root forward
+    pub root_advances: usize,
+    //votes applied across the steps
+    pub votes: usize,
+    //fork count after the last step
+    pub fork_count: usize,
+    //the invariant violated after each step that broke one
+    pub violations: Vec<(Slot, InvariantViolation)>,
 }
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DiffError {
+    //the networks are at different slots
+    SlotMismatch(Slot, Slot),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum UndoError {
+    //every step kept in the undo history has been undone
+    NoHistory,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum InitialTowerError {
+    //the network has already stepped past genesis
+    NotGenesis(Slot),
+    //the tower's root is not the genesis slot
+    Root(Vote),
+    //the tower's lockout base differs from the config's
+    Base(u64),
+    Tower(TowerError),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PartitionError {
+    //fractions are empty or don't sum to 1.0
+    InvalidFractions,
+    //the partition at this index would have no nodes
+    EmptyPartition(usize),
+    //the node is in more than one partition, or twice in one
+    DuplicateNode(ID),
+    //the node isn't in any partition
+    MissingNode(ID),
+    //the id isn't one of the NUM_NODES nodes
+    UnknownNode(ID),
+}
+
 impl Default for Network {
     fn default() -> Self {
+        Self::with_config(Config::default())
+    }
+}
+impl Network {
+    //seed for the subcommittee draws
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_config(Config {
+            seed,
+            ..Config::default()
+        })
+    }
+    pub fn with_leader_schedule(leader_schedule: LeaderSchedule) -> Self {
+        Self::with_config(Config {
+            leader_schedule,
+            ..Config::default()
+        })
+    }
+    //each slot has chance p of getting no block
+    pub fn with_skip_rate(p: f64) -> Self {
+        Self::with_config(Config {
+            skip_rate: p,
+            ..Config::default()
+        })
+    }
+    //each slot's leader has chance p of failing to produce its block
+    pub fn with_leader_failure_rate(p: f64) -> Self {
+        Self::with_config(Config {
+            leader_failure_rate: p,
+            ..Config::default()
+        })
+    }
+    //leaders only include the votes the policy picks
+    pub fn with_inclusion_policy(inclusion_policy: InclusionPolicy) -> Self {
+        Self::with_config(Config {
+            inclusion_policy,
+            ..Config::default()
+        })
+    }
+    pub fn with_config(config: Config) -> Self {
+        config.validate();
         let mut nodes = vec![];
         for i in 0..NUM_NODES {
-            nodes.push(Node::zero(i));
+            nodes.push(Self::genesis_node(&config, i));
         }
         Network {
-            banks: Banks::default(),
+            banks: Self::genesis_banks(&config),
+            config,
             nodes,
             slot: 0,
             num_partitions: 0,
+            partition_map: vec![],
+            reachability: vec![],
             partitioned_blocks: VecDeque::new(),
+            delay_slots: 0,
+            in_flight: BTreeMap::new(),
+            rejections: vec![],
+            blocked_switches: 0,
+            last_step_votes: 0,
+            total_votes: 0,
+            recording: None,
+            last_block: None,
+            healed: vec![],
+            heal_rate: 0.0,
+            heal_progress: 0.0,
+            root_callbacks: vec![],
+            block_mutator: None,
+            apply_errors: vec![],
+            undo_history: VecDeque::new(),
+            undo_depth: 1,
+            event_stream: None,
+            repaired_at: None,
+            converged_at: None,
+            heaviest_tip: None,
+            reorgs: vec![],
         }
     }
-}
-impl Network {
-    fn hash(val: u64) -> u64 {
-        let mut h = DefaultHasher::new();
-        val.hash(&mut h);
-        h.finish()
+    fn genesis_node(config: &Config, id: ID) -> Node {
+        let mut node = Node::with_lockout_base(id, config.lockout_base);
+        node.threshold = config.threshold;
+        node.supermajority = config.supermajority;
+        node.vote_lag = config.vote_lag;
+        node
     }
-    fn check_same_partition(num_partitions: usize, a: ID, b: ID) -> bool {
+    fn genesis_banks(config: &Config) -> Banks {
+        let subcom = Subcommittee::new(
+            config.seed,
+            config.subcommittee_hash,
+            config.subcommittee_size,
+        );
+        let mut banks = Banks::with_subcommittee(subcom, config.lockout_base);
+        banks.max_children = config.max_children;
+        banks.threshold = config.threshold;
+        banks.supermajority = config.supermajority;
+        banks.stakes = config.node_stakes();
+        for bank in banks.fork_map.values_mut() {
+            bank.supermajority = config.supermajority;
+            bank.stakes = banks.stakes.clone();
+        }
+        banks.retain_depth = config.retain_depth;
+        banks
+    }
+    //back to the state with_config would build, reusing the nodes vec
+    pub fn reset(&mut self) {
+        for n in self.nodes.iter_mut() {
+            *n = Self::genesis_node(&self.config, n.id);
+        }
+        self.banks = Self::genesis_banks(&self.config);
+        self.slot = 0;
+        self.num_partitions = 0;
+        self.partition_map.clear();
+        self.reachability.clear();
+        self.partitioned_blocks.clear();
+        self.delay_slots = 0;
+        self.in_flight.clear();
+        self.rejections.clear();
+        self.blocked_switches = 0;
+        self.last_step_votes = 0;
+        self.total_votes = 0;
+        self.recording = None;
+        self.last_block = None;
+        self.healed.clear();
+        self.heal_rate = 0.0;
+        self.heal_progress = 0.0;
+        self.root_callbacks.clear();
+        self.block_mutator = None;
+        self.apply_errors.clear();
+        self.undo_history.clear();
+        self.undo_depth = 1;
+        self.event_stream = None;
+        self.repaired_at = None;
+        self.converged_at = None;
+        self.heaviest_tip = None;
+        self.reorgs.clear();
+    }
+    fn check_same_partition(num_partitions: usize, partition_map: &[usize], a: ID, b: ID) -> bool {
+        if !partition_map.is_empty() {
+            return partition_map[a] == partition_map[b];
+        }
         num_partitions == 0 || (a % num_partitions == b % num_partitions)
     }
+    fn partition(&self, id: ID) -> usize {
+        if !self.partition_map.is_empty() {
+            return self.partition_map[id];
+        }
+        id % self.num_partitions
+    }
+    //None when the network isn't partitioned. nodes healed by a gradual
+    //repair keep their partition but see every other one
+    pub fn partition_of(&self, id: ID) -> Option<usize> {
+        if self.num_partitions == 0 {
+            return None;
+        }
+        Some(self.partition(id))
+    }
+    //the nodes in each partition, a single one with every node when
+    //the network isn't partitioned
+    pub fn partitions(&self) -> Vec<Vec<ID>> {
+        let mut partitions = vec![vec![]; self.num_partitions.max(1)];
+        for id in 0..NUM_NODES {
+            partitions[self.partition_of(id).unwrap_or(0)].push(id);
+        }
+        partitions
+    }
+    //no partition has a supermajority, so super_root can't advance until
+    //the partitions are repaired. lowest_root stops in any split, since
+    //the smaller sides hold it back, and the banks above it are never
+    //gc'd, a split left up grows them every slot
+    pub fn partition_stalled(&self) -> bool {
+        let needed = supermajority_stake(self.banks.total_stake(), self.config.supermajority);
+        self.num_partitions > 1
+            && self
+                .partitions()
+                .iter()
+                .all(|p| p.iter().map(|id| self.banks.stake(*id)).sum::<usize>() <= needed)
+    }
+    //b sees a's blocks and votes
+    fn connected(&self, a: ID, b: ID) -> bool {
+        self.healed.get(a).copied().unwrap_or(false)
+            || self.healed.get(b).copied().unwrap_or(false)
+            || Self::check_same_partition(self.num_partitions, &self.partition_map, a, b)
+            || (!self.reachability.is_empty()
+                && self.reachability[self.partition(a)][self.partition(b)])
+    }
     pub fn create_partitions(&mut self, num: usize) {
         self.num_partitions = num;
+        self.partition_map = vec![];
+        self.reachability = vec![];
+    }
+    //split the nodes into partitions a (even ids) and b (odd ids)
+    //with one-way links, e.g. a_sees_b without b_sees_a models a
+    //group that receives b's blocks but whose own don't reach b
+    pub fn create_asymmetric_partition(&mut self, a_sees_b: bool, b_sees_a: bool) {
+        self.create_partitions(2);
+        self.reachability = vec![vec![true, b_sees_a], vec![a_sees_b, true]];
+    }
+    //split the nodes into contiguous partitions sized by fraction of
+    //the node count, e.g. &[0.4, 0.35, 0.25]
+    pub fn create_partitions_weighted(&mut self, fractions: &[f64]) -> Result<(), PartitionError> {
+        let total: f64 = fractions.iter().sum();
+        if fractions.is_empty() || (total - 1.0).abs() > 1e-6 {
+            return Err(PartitionError::InvalidFractions);
+        }
+        let mut partition_map = vec![];
+        let mut cumulative = 0.0;
+        for (p, f) in fractions.iter().enumerate() {
+            cumulative += f;
+            let end = if p + 1 == fractions.len() {
+                NUM_NODES
+            } else {
+                (cumulative * NUM_NODES as f64).round() as usize
+            };
+            if end <= partition_map.len() {
+                return Err(PartitionError::EmptyPartition(p));
+            }
+            partition_map.resize(end, p);
+        }
+        self.num_partitions = fractions.len();
+        self.partition_map = partition_map;
+        self.reachability = vec![];
+        Ok(())
+    }
+    //split the nodes into exactly these partitions, every node has to be
+    //in one of them
+    pub fn create_partitions_explicit(
+        &mut self,
+        partitions: &[Vec<ID>],
+    ) -> Result<(), PartitionError> {
+        let mut partition_map = vec![None; NUM_NODES];
+        for (p, ids) in partitions.iter().enumerate() {
+            if ids.is_empty() {
+                return Err(PartitionError::EmptyPartition(p));
+            }
+            for id in ids {
+                match partition_map.get_mut(*id) {
+                    None => return Err(PartitionError::UnknownNode(*id)),
+                    Some(Some(_)) => return Err(PartitionError::DuplicateNode(*id)),
+                    Some(slot) => *slot = Some(p),
+                }
+            }
+        }
+        let partition_map = partition_map
+            .into_iter()
+            .enumerate()
+            .map(|(id, p)| p.ok_or(PartitionError::MissingNode(id)))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.num_partitions = partitions.len();
+        self.partition_map = partition_map;
+        self.reachability = vec![];
+        Ok(())
     }
     pub fn repair_partitions(&mut self, new_partitions: usize) {
         for (block_producer_ix, block) in &self.partitioned_blocks {
             self.nodes.iter_mut().enumerate().for_each(|(i, n)| {
-                if Self::check_same_partition(new_partitions, *block_producer_ix, i) {
+                if Self::check_same_partition(new_partitions, &[], *block_producer_ix, i) {
                     n.set_active_block(*block);
                 }
             });
         }
         self.num_partitions = new_partitions;
+        self.partition_map = vec![];
+        self.reachability = vec![];
+        self.healed.clear();
+        self.heal_rate = 0.0;
+        self.heal_progress = 0.0;
+        self.repaired_at = Some(self.slot);
+        self.converged_at = None;
+    }
+    //every online node's heaviest fork has the same tip. halted nodes
+    //don't vote, so their heaviest fork is stale
+    pub fn is_converged(&self) -> bool {
+        let mut tips = self
+            .nodes
+            .iter()
+            .filter(|n| !n.halted)
+            .map(|n| n.heaviest_fork[0]);
+        let first = tips.next();
+        tips.all(|t| Some(t) == first)
+    }
+    //steps from the last repair_partitions until the nodes converged,
+    //None if there was no repair or they haven't converged yet
+    pub fn steps_to_convergence(&self) -> Option<usize> {
+        Some((self.converged_at? - self.repaired_at?) as usize)
+    }
+    //reconnect rate * NUM_NODES nodes to every partition on each step
+    //until all of them are, at which point the partitions are repaired
+    pub fn repair_partitions_gradual(&mut self, rate: f64) {
+        assert!(rate > 0.0 && rate <= 1.0, "invalid heal rate {}", rate);
+        self.healed = vec![false; NUM_NODES];
+        self.heal_rate = rate;
+        self.heal_progress = 0.0;
+    }
+    fn heal(&mut self) {
+        if self.heal_rate == 0.0 {
+            return;
+        }
+        self.heal_progress =
+            (self.heal_progress + self.heal_rate * NUM_NODES as f64).min(NUM_NODES as f64);
+        //heal in a fixed pseudo random order so every partition heals
+        let mut order: Vec<ID> = (0..NUM_NODES).collect();
+        order.sort_by_key(|i| splitmix64(*i as u64));
+        for id in order.into_iter().take(self.heal_progress as usize) {
+            if self.healed[id] {
+                continue;
+            }
+            self.healed[id] = true;
+            for (_, block) in &self.partitioned_blocks {
+                self.nodes[id].set_active_block(*block);
+            }
+        }
+        if self.heal_progress as usize == NUM_NODES {
+            self.repair_partitions(0);
+        }
+    }
+    pub fn set_delay(&mut self, delay_slots: usize) {
+        self.delay_slots = delay_slots;
+    }
+    //deliver the in-flight blocks that are due to the nodes
+    //in the same partition as the block producer
+    fn deliver(&mut self) {
+        let pending = self.in_flight.split_off(&(self.slot + 1));
+        let due = std::mem::replace(&mut self.in_flight, pending);
+        for (block_producer_ix, block) in due.into_values().flatten() {
+            let visible: Vec<_> = (0..self.nodes.len())
+                .map(|i| self.connected(block_producer_ix, i))
+                .collect();
+            for (n, visible) in self.nodes.iter_mut().zip(visible) {
+                if visible {
+                    n.set_active_block(block);
+                }
+            }
+        }
+    }
+    //callbacks run in the order they are registered, after the block
+    //that advanced the root is applied
+    pub fn on_root_advance(&mut self, callback: Box<dyn FnMut(Vote)>) {
+        self.root_callbacks.push(callback);
+    }
+    //for fault injection, the mutator can change each produced block
+    //before the banks apply it. blocks the banks reject are skipped and
+    //show up in apply_errors
+    pub fn set_block_mutator(&mut self, mutator: BlockMutator) {
+        self.block_mutator = Some(mutator);
+    }
+    //blocks the banks rejected since the last reset
+    pub fn apply_errors(&self) -> &[(Slot, ApplyError)] {
+        &self.apply_errors
+    }
+    //writes one json line per step with the slot, the leader (null when
+    //no block was applied), the root, the fork count and the step's
+    //vote rejections. the stream is dropped after a write error
+    pub fn enable_event_stream(&mut self, writer: Box<dyn Write>) {
+        self.event_stream = Some(writer);
+    }
+    pub fn set_fork_choice(&mut self, id: ID, fork_choice: Box<dyn ForkChoice>) {
+        self.nodes[id].fork_choice = fork_choice.into();
+    }
+    //take a node offline for good, it stops voting and producing
+    //blocks but the votes the banks already have from it still count.
+    //lowest_root stops at the halted node's root, the supermajority
+    //root keeps advancing as long as less than 1/3 of nodes are halted
+    pub fn halt_node(&mut self, id: ID) {
+        self.nodes[id].halted = true;
+    }
+    pub fn resume_node(&mut self, id: ID) {
+        self.nodes[id].halted = false;
+    }
+    //see Node::catch_up
+    pub fn catch_up(&mut self, id: ID) -> Result<(), CatchUpError> {
+        self.nodes[id].catch_up(&self.banks)
+    }
+    //skew the node's clock by offset slots, see Node::slot_offset
+    pub fn set_slot_offset(&mut self, id: ID, offset: i64) {
+        self.nodes[id].slot_offset = offset;
+    }
+    //starts the node with tower instead of an empty one, e.g. already
+    //locked out on slots that haven't been produced yet. the banks only
+    //see its votes once a block includes them
+    pub fn set_initial_tower(&mut self, id: ID, tower: Tower) -> Result<(), InitialTowerError> {
+        if self.slot != 0 {
+            return Err(InitialTowerError::NotGenesis(self.slot));
+        }
+        if tower.root.slot != 0 {
+            return Err(InitialTowerError::Root(tower.root));
+        }
+        if tower.base != self.config.lockout_base {
+            return Err(InitialTowerError::Base(tower.base));
+        }
+        tower.validate().map_err(InitialTowerError::Tower)?;
+        self.nodes[id].set_tower(tower);
+        Ok(())
+    }
+    //the node checks lockouts of base^threshold and above, a threshold
+    //of DEPTH turns its check off, models a partially upgraded network
+    pub fn set_node_threshold(&mut self, id: ID, threshold: usize) {
+        assert!(
+            threshold > 0 && threshold <= DEPTH,
+            "node threshold {} must be in 1..={}",
+            threshold,
+            DEPTH
+        );
+        self.nodes[id].threshold = threshold;
+    }
+    //see Node::block_gc_threshold
+    pub fn set_block_gc_threshold(&mut self, threshold: usize) {
+        for n in &mut self.nodes {
+            n.block_gc_threshold = threshold;
+        }
+    }
+    //these nodes still vote but never produce blocks, their slots go
+    //to the next node in the schedule
+    pub fn set_non_leader(&mut self, ids: &[ID]) {
+        for n in &mut self.nodes {
+            n.non_leader = ids.contains(&n.id);
+        }
+    }
+    pub fn set_byzantine(&mut self, ids: &[ID]) {
+        for n in &mut self.nodes {
+            n.equivocate = ids.contains(&n.id);
+        }
+    }
+    pub fn snapshot(&self) -> NetworkSnapshot {
+        NetworkSnapshot {
+            config: self.config.clone(),
+            slot: self.slot,
+            num_partitions: self.num_partitions,
+            partition_map: self.partition_map.clone(),
+            reachability: self.reachability.clone(),
+            partitioned_blocks: self.partitioned_blocks.iter().copied().collect(),
+            delay_slots: self.delay_slots,
+            in_flight: self
+                .in_flight
+                .iter()
+                .flat_map(|(at, blocks)| blocks.iter().map(move |(id, b)| (*at, *id, *b)))
+                .collect(),
+            banks: self.banks.snapshot(),
+            healed: (0..self.healed.len()).filter(|i| self.healed[*i]).collect(),
+            heal_rate: self.heal_rate,
+            heal_progress: self.heal_progress,
+            nodes: self.nodes.iter().map(|n| n.snapshot()).collect(),
+        }
+    }
+    //fails if the snapshot fails NetworkSnapshot::check
+    pub fn from_snapshot(snap: NetworkSnapshot) -> Result<Self, SnapshotError> {
+        snap.check()?;
+        let banks = Banks::from_snapshot(snap.banks, &snap.config)?;
+        let supermajority = snap.config.supermajority;
+        let vote_lag = snap.config.vote_lag;
+        let mut healed = vec![];
+        if snap.heal_rate > 0.0 {
+            healed = vec![false; NUM_NODES];
+            for id in snap.healed {
+                healed[id] = true;
+            }
+        }
+        let mut nodes = Vec::with_capacity(NUM_NODES);
+        for n in snap.nodes {
+            let mut node = Node::from_snapshot(n)?;
+            node.supermajority = supermajority;
+            node.vote_lag = vote_lag;
+            nodes.push(node);
+        }
+        Ok(Network {
+            config: snap.config,
+            banks,
+            nodes,
+            slot: snap.slot,
+            num_partitions: snap.num_partitions,
+            partition_map: snap.partition_map,
+            reachability: snap.reachability,
+            partitioned_blocks: snap.partitioned_blocks.into_iter().collect(),
+            delay_slots: snap.delay_slots,
+            in_flight: snap
+                .in_flight
+                .into_iter()
+                .fold(BTreeMap::new(), |mut m, (at, id, b)| {
+                    m.entry(at).or_insert_with(Vec::new).push((id, b));
+                    m
+                }),
+            rejections: vec![],
+            blocked_switches: 0,
+            last_step_votes: 0,
+            total_votes: 0,
+            recording: None,
+            last_block: None,
+            healed,
+            heal_rate: snap.heal_rate,
+            heal_progress: snap.heal_progress,
+            root_callbacks: vec![],
+            block_mutator: None,
+            apply_errors: vec![],
+            undo_history: VecDeque::new(),
+            undo_depth: 1,
+            event_stream: None,
+            repaired_at: None,
+            converged_at: None,
+            heaviest_tip: None,
+            reorgs: vec![],
+        })
+    }
+    //checkpoints of a network with a custom fork choice rule or a
+    //subcommittee hash other than default_hash or splitmix64 fail to
+    //load. a loaded network steps like the saved one would have, but
+    //the metrics that aren't in the snapshot restart: total_votes,
+    //rejections, last_block, repaired_at, converged_at and reorg_events
+    pub fn save_checkpoint(&self, path: &str) -> std::io::Result<()> {
+        fs::write(path, self.snapshot().to_json())
+    }
+    pub fn load_checkpoint(path: &str) -> Result<Self, SnapshotError> {
+        let text = fs::read_to_string(path).map_err(|e| SnapshotError {
+            message: format!("{}: {}", path, e),
+        })?;
+        Self::from_snapshot(NetworkSnapshot::from_json(&text)?)
+    }
+    //a default network weighted by the stake distribution in a
+    //validator_id,stake csv. validator id goes to node id % NUM_NODES,
+    //so a node stands in for every NUM_NODES-th validator of a larger
+    //set, and with fewer validators the nodes left over have no stake
+    pub fn from_stake_csv(path: &str) -> Result<Self, StakeError> {
+        let mut stakes = vec![0u64; NUM_NODES];
+        for (id, stake) in StakeDistribution::load(path)?.stakes.iter().enumerate() {
+            //an overflow fails Config::check
+            stakes[id % NUM_NODES] = stakes[id % NUM_NODES].saturating_add(*stake);
+        }
+        let config = Config {
+            stakes,
+            ..Config::default()
+        };
+        config
+            .check()
+            .map_err(|message| StakeError { line: 0, message })?;
+        Ok(Self::with_config(config))
+    }
+    pub fn run_scenario(&mut self, scenario: &Scenario) -> Result<(), ScenarioError> {
+        for (line, command) in &scenario.commands {
+            match command {
+                Command::Step(n) => {
+                    for _ in 0..*n {
+                        self.step();
+                    }
+                }
+                Command::Partition(n) => self.create_partitions(*n),
+                Command::PartitionWeighted(fractions) => self
+                    .create_partitions_weighted(fractions)
+                    .map_err(|e| ScenarioError {
+                        line: *line,
+                        message: format!("{:?}", e),
+                    })?,
+                Command::Repair(n) => self.repair_partitions(*n),
+                Command::Byzantine(ids) => self.set_byzantine(ids),
+                Command::Delay(n) => self.set_delay(*n),
+            }
+        }
+        Ok(())
+    }
+    //node_id,slot,lockout for the root and every vote in each node's
+    //tower, ordered by node id then slot
+    pub fn export_votes_csv(&self) -> String {
+        let mut csv = String::from("node_id,slot,lockout\n");
+        for n in &self.nodes {
+            for v in n.tower().votes() {
+                csv += &format!("{},{},{}\n", n.id, v.slot, v.lockout);
+            }
+        }
+        csv
+    }
+    //switching proof counts summed over all the nodes since the last reset
+    pub fn switch_stats(&self) -> SwitchStats {
+        let mut stats = SwitchStats::default();
+        for n in &self.nodes {
+            stats += n.switch_stats();
+        }
+        stats
+    }
+    //vote opportunities lost to rejected votes, summed over all the
+    //nodes since the last reset
+    pub fn wasted_vote_slots(&self) -> usize {
+        self.nodes.iter().map(|n| n.wasted_vote_slots()).sum()
+    }
+    //times the node's threshold check result flipped between
+    //consecutive vote attempts since the last reset
+    pub fn threshold_oscillations(&self, id: ID) -> usize {
+        self.nodes[id].threshold_oscillations()
+    }
+    //the latest block applied by step, its slot is older than the
+    //network's when the slots after it were skipped
+    pub fn last_block(&self) -> Option<&Block> {
+        self.last_block.as_ref()
+    }
+    //votes applied by the last step's block, near 0 when the network stalls
+    pub fn last_step_votes(&self) -> usize {
+        self.last_step_votes
+    }
+    //votes applied since the last reset
+    pub fn total_votes(&self) -> usize {
+        self.total_votes
+    }
+    //keeps every block applied from now on, for Banks::replay
+    pub fn start_recording(&mut self) {
+        self.recording = Some(vec![]);
+    }
+    //the blocks recorded so far, recording continues with an empty list
+    pub fn take_recording(&mut self) -> Vec<Block> {
+        self.recording
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+    pub fn drain_rejections(&mut self) -> Vec<VoteRejection> {
+        std::mem::take(&mut self.rejections)
+    }
+    //(tower height, number of nodes) sorted by height, empty heights
+    //are left out
+    pub fn tower_height_histogram(&self) -> Vec<(usize, usize)> {
+        let mut counts = [0; DEPTH + 1];
+        for n in &self.nodes {
+            counts[n.tower().votes.len()] += 1;
+        }
+        (0..counts.len())
+            .filter(|h| counts[*h] > 0)
+            .map(|h| (h, counts[h]))
+            .collect()
+    }
+    //the heaviest fork over every bank, ignoring partitions, tip first
+    //like Node::heaviest_fork
+    pub fn heaviest_fork(&self) -> Vec<Slot> {
+        let slot = HeaviestFork.choose(&self.banks.fork_weights, &self.banks);
+        self.banks.compute_fork(slot)
+    }
+    //nodes whose heaviest fork tip isn't on heaviest_fork, the nodes on
+    //a minority fork. nodes vote before the leader's block, so a tip
+    //behind the network's tip on the same fork isn't divergent
+    pub fn divergent_nodes(&self) -> Vec<ID> {
+        let fork: HashSet<Slot> = self.heaviest_fork().into_iter().collect();
+        self.nodes
+            .iter()
+            .filter(|n| n.heaviest_fork.first().is_some_and(|t| !fork.contains(t)))
+            .map(|n| n.id)
+            .collect()
+    }
+    //(old tip, new tip) of the heaviest fork for every step that moved
+    //it to a fork that doesn't descend from the old tip, since the last
+    //reset
+    pub fn reorg_events(&self) -> Vec<(Slot, Slot)> {
+        self.reorgs.iter().map(|(_, reorg)| *reorg).collect()
+    }
+    //checks each cause in order and returns the first that applies
+    pub fn root_stall_reason(&self) -> StallReason {
+        let fork = self.heaviest_fork();
+        let weight = *self.banks.fork_weights.get(&fork[0]).unwrap_or(&0);
+        let total = self.banks.total_stake();
+        if weight <= supermajority_stake(total, self.config.supermajority) {
+            return StallReason::NoSupermajority { weight };
+        }
+        let locked: Vec<ID> = self
+            .nodes
+            .iter()
+            .filter(|n| n.tower().votes.iter().any(|v| !fork.contains(&v.slot)))
+            .map(|n| n.id)
+            .collect();
+        let stake: usize = locked.iter().map(|id| self.banks.stake(*id)).sum();
+        if stake > minority_stake(total, self.config.supermajority) {
+            return StallReason::LockedOut {
+                nodes: locked.len(),
+            };
+        }
+        if self.blocked_switches > NUM_NODES / 2 {
+            return StallReason::SwitchBlocked {
+                nodes: self.blocked_switches,
+            };
+        }
+        StallReason::Other
+    }
+    pub fn check_invariants(&self) -> Result<(), InvariantViolation> {
+        let lowest_root = self.banks.lowest_root.slot;
+        //roots below lowest_root are gc'd and can't be compared
+        let mut roots: Vec<_> = self
+            .banks
+            .fork_map
+            .values()
+            .map(|b| b.calc_super_root().slot)
+            .filter(|x| *x >= lowest_root)
+            .collect();
+        roots.sort_unstable();
+        roots.dedup();
+        for (i, a) in roots.iter().enumerate() {
+            for b in &roots[i + 1..] {
+                if !self.banks.compute_fork(*b).contains(a) {
+                    return Err(InvariantViolation::ConflictingRoots { a: *a, b: *b });
+                }
+            }
+        }
+        for n in &self.nodes {
+            let root = n.tower().root.slot;
+            if root < lowest_root {
+                return Err(InvariantViolation::NodeBelowLowestRoot {
+                    id: n.id,
+                    root,
+                    lowest_root,
+                });
+            }
+        }
+        for (slot, weight) in &self.banks.fork_weights {
+            if *weight > self.banks.total_stake() {
+                return Err(InvariantViolation::ForkWeightExceedsStake {
+                    slot: *slot,
+                    weight: *weight,
+                });
+            }
+        }
+        Ok(())
+    }
+    //a non leader if every node in the schedule is one
+    pub fn leader_for(&self, slot: Slot) -> ID {
+        let schedule = &self.config.leader_schedule;
+        schedule
+            .leader_among(slot, |id| !self.nodes[id].non_leader)
+            .unwrap_or_else(|| schedule.leader(slot))
+    }
+    //slots in skip_slots or drawn by skip_rate get no block
+    pub fn is_skipped(&self, slot: Slot) -> bool {
+        if self.config.skip_slots.contains(&slot) {
+            return true;
+        }
+        self.draw(splitmix64(slot)) < self.config.skip_rate
+    }
+    //whether the node votes on the slot, drawn with the participation rate
+    pub fn participates(&self, id: ID, slot: Slot) -> bool {
+        self.config.participation >= 1.0
+            || self.draw(splitmix64(slot).wrapping_add(id as u64 + 1)) < self.config.participation
+    }
+    //whether the slot's leader crashes or is censored instead of
+    //producing its block, drawn with the leader failure rate
+    pub fn leader_fails(&self, slot: Slot) -> bool {
+        let leader = self.leader_for(slot) as u64;
+        self.draw(splitmix64(splitmix64(slot) ^ leader)) < self.config.leader_failure_rate
+    }
+    //uniform in 0.0..=1.0, fixed by the seed and the key
+    fn draw(&self, key: u64) -> f64 {
+        splitmix64(self.config.seed ^ key) as f64 / u64::MAX as f64
     }
     pub fn root(&self) -> Vote {
         self.banks.lowest_root
     }
+    //highest supermajority root frozen into a bank, it drives the
+    //subcommittee rotation while root drives gc, see Subcommittee::freeze
+    pub fn super_root(&self) -> Slot {
+        self.banks
+            .fork_map
+            .values()
+            .map(|b| b.subcom.super_root)
+            .max()
+            .unwrap_or(self.banks.lowest_root.slot)
+    }
+    //how far root lags behind super_root, it grows while a halted or
+    //partitioned minority holds the lowest root back
+    pub fn super_root_gap(&self) -> Slot {
+        self.super_root().saturating_sub(self.root().slot)
+    }
+    //longest fork abandoned so far, see Banks::max_orphan_depth
+    pub fn max_orphan_depth(&self) -> usize {
+        self.banks.max_orphan_depth
+    }
+    //forks alive from the root, see Banks::fork_count
+    pub fn fork_count(&self) -> usize {
+        self.banks.fork_count()
+    }
+    //the root in the node's own tower
+    pub fn node_root(&self, id: ID) -> Vote {
+        self.nodes[id].tower().root
+    }
+    //(min, max) of the node roots, equal when every node agrees
+    pub fn root_spread(&self) -> (Slot, Slot) {
+        let roots = self.nodes.iter().map(|n| n.tower().root.slot);
+        (roots.clone().min().unwrap(), roots.max().unwrap())
+    }
+    //compares two runs at the same step, e.g. the same seed with a
+    //different config
+    pub fn diff(&self, other: &Network) -> Result<RunDiff, DiffError> {
+        if self.slot != other.slot {
+            return Err(DiffError::SlotMismatch(self.slot, other.slot));
+        }
+        let mut diff = RunDiff {
+            root: other.root().slot as i64 - self.root().slot as i64,
+            fork_count: other.fork_count() as i64 - self.fork_count() as i64,
+            ..RunDiff::default()
+        };
+        for (a, b) in self.nodes.iter().zip(&other.nodes) {
+            let gap = a.tower().root.slot.abs_diff(b.tower().root.slot);
+            if gap > 0 {
+                diff.differing_roots += 1;
+            }
+            diff.max_root_gap = diff.max_root_gap.max(gap);
+        }
+        Ok(diff)
+    }
+    //how much more stake can go offline with the online stake still a
+    //supermajority, which threshold_slot and calc_super_root need. with
+    //equal stakes it's NUM_NODES / 3 nodes at the default 2/3 while
+    //none are halted
+    pub fn fault_tolerance(&self) -> usize {
+        let online: usize = (self.nodes.iter())
+            .filter(|n| !n.halted)
+            .map(|n| self.banks.stake(n.id))
+            .sum();
+        online.saturating_sub(
+            supermajority_stake(self.banks.total_stake(), self.config.supermajority) + 1,
+        )
+    }
+    //number of steps undo_step can revert, 1 by default. each step
+    //copies the nodes and the banks' bookkeeping while it's above 0, the
+    //banks' towers are shared. set_undo_depth(0) turns that off for
+    //long runs
+    pub fn set_undo_depth(&mut self, depth: usize) {
+        self.undo_depth = depth;
+        while self.undo_history.len() > depth {
+            self.undo_history.pop_front();
+        }
+    }
+    //back to the state before the last step, counters included. fork
+    //choice rules, root callbacks, the block mutator and the event
+    //stream are kept. the logs lose what the undone step added:
+    //rejections, apply_errors, reorg_events and the recording
+    pub fn undo_step(&mut self) -> Result<(), UndoError> {
+        let (mut restored, rejected) = self.undo_history.pop_back().ok_or(UndoError::NoHistory)?;
+        for (n, old) in restored.nodes.iter_mut().zip(&self.nodes) {
+            n.fork_choice = old.fork_choice.clone();
+        }
+        restored.rejections = std::mem::take(&mut self.rejections);
+        restored.rejections.truncate(rejected);
+        restored.root_callbacks = std::mem::take(&mut self.root_callbacks);
+        restored.block_mutator = self.block_mutator.take();
+        restored.apply_errors = std::mem::take(&mut self.apply_errors);
+        restored
+            .apply_errors
+            .retain(|(slot, _)| *slot <= restored.slot);
+        restored.undo_history = std::mem::take(&mut self.undo_history);
+        restored.undo_depth = self.undo_depth;
+        restored.reorgs = std::mem::take(&mut self.reorgs);
+        restored.reorgs.retain(|(slot, _)| *slot <= restored.slot);
+        restored.event_stream = self.event_stream.take();
+        restored.recording = self.recording.take();
+        if let Some(recording) = &mut restored.recording {
+            recording.retain(|b| b.slot <= restored.slot);
+        }
+        *self = restored;
+        Ok(())
+    }
+    //a copy of the network for the undo history. the logs that only
+    //grow and the hooks stay behind, undo_step takes them from the
+    //network it replaces
+    fn undo_state(&self) -> Network {
+        Network {
+            config: self.config.clone(),
+            nodes: self.nodes.clone(),
+            banks: self.banks.clone(),
+            slot: self.slot,
+            num_partitions: self.num_partitions,
+            partition_map: self.partition_map.clone(),
+            reachability: self.reachability.clone(),
+            partitioned_blocks: self.partitioned_blocks.clone(),
+            delay_slots: self.delay_slots,
+            in_flight: self.in_flight.clone(),
+            rejections: vec![],
+            blocked_switches: self.blocked_switches,
+            last_step_votes: self.last_step_votes,
+            total_votes: self.total_votes,
+            recording: None,
+            last_block: self.last_block.clone(),
+            healed: self.healed.clone(),
+            heal_rate: self.heal_rate,
+            heal_progress: self.heal_progress,
+            root_callbacks: vec![],
+            block_mutator: None,
+            apply_errors: vec![],
+            undo_history: VecDeque::new(),
+            undo_depth: 0,
+            event_stream: None,
+            repaired_at: self.repaired_at,
+            converged_at: self.converged_at,
+            heaviest_tip: self.heaviest_tip,
+            reorgs: vec![],
+        }
+    }
     pub fn step(&mut self) {
-        self.slot = self.slot + 1;
-        println!("slot {} voting", self.slot);
-        self.nodes.iter_mut().for_each(|n| n.vote(&self.banks));
-        let block_producer_ix = Self::hash(self.slot) as usize % self.nodes.len();
+        if self.undo_depth > 0 {
+            if self.undo_history.len() == self.undo_depth {
+                self.undo_history.pop_front();
+            }
+            let state = (self.undo_state(), self.rejections.len());
+            self.undo_history.push_back(state);
+        }
+        let rejected = self.rejections.len();
+        let leader = self.produce();
+        if self.repaired_at.is_some() && self.converged_at.is_none() && self.is_converged() {
+            self.converged_at = Some(self.slot);
+        }
+        let tip = self.heaviest_fork()[0];
+        if let Some(old) = self.heaviest_tip {
+            //tips at or below the root were gc'd out of the fork
+            if old > self.root().slot && !self.banks.compute_fork(tip).contains(&old) {
+                self.reorgs.push((self.slot, (old, tip)));
+            }
+        }
+        self.heaviest_tip = Some(tip);
+        if let Some(stream) = &mut self.event_stream {
+            let event = StepEvent {
+                slot: self.slot,
+                leader,
+                root: self.banks.lowest_root,
+                fork_count: self.banks.fork_count(),
+                rejections: self.rejections[rejected..]
+                    .iter()
+                    .map(|r| r.into())
+                    .collect(),
+            };
+            let written = serde_json::to_writer(&mut *stream, &event)
+                .map_err(std::io::Error::from)
+                .and_then(|_| writeln!(stream));
+            if let Err(e) = written {
+                log::error!("event stream write failed: {}", e);
+                self.event_stream = None;
+            }
+        }
+    }
+    //steps n slots, checking the invariants after each one
+    pub fn step_n(&mut self, n: usize) -> StepSummary {
+        let mut summary = StepSummary::default();
+        for _ in 0..n {
+            let root = self.root();
+            self.step();
+            if self.root().slot > root.slot {
+                summary.root_advances += 1;
+            }
+            summary.votes += self.last_step_votes;
+            if let Err(violation) = self.check_invariants() {
+                summary.violations.push((self.slot, violation));
+            }
+        }
+        summary.fork_count = self.fork_count();
+        summary
+    }
+    //votes and applies the slot's block, returns the leader if the
+    //block was applied
+    fn produce(&mut self) -> Option<ID> {
+        self.slot += 1;
+        self.heal();
+        log::debug!("slot={} voting", self.slot);
+        self.blocked_switches = 0;
+        self.last_step_votes = 0;
+        let voters: Vec<bool> = (0..NUM_NODES)
+            .map(|id| self.participates(id, self.slot))
+            .collect();
+        for n in self.nodes.iter_mut() {
+            if !voters[n.id] {
+                continue;
+            }
+            if let Some(rejection) = n.vote(&self.banks, self.slot) {
+                if rejection.reason == RejectReason::OptimisticConfirmation {
+                    self.blocked_switches += 1;
+                }
+                self.rejections.push(rejection);
+            }
+        }
+        if self.is_skipped(self.slot) {
+            self.deliver();
+            return None;
+        }
+        let block_producer_ix = self.leader_for(self.slot);
         let block_producer = &self.nodes[block_producer_ix];
+        if block_producer.halted || block_producer.non_leader || self.leader_fails(self.slot) {
+            //the leader is offline, failed or there is no eligible
+            //leader, this slot is skipped
+            self.deliver();
+            return None;
+        }
         let votes: Vec<_> = self
             .nodes
             .iter()
             .enumerate()
-            .filter_map(|(i, n)| {
-                if !Self::check_same_partition(self.num_partitions, block_producer_ix, i) {
-                    return None;
+            .flat_map(|(i, n)| {
+                if n.halted {
+                    return vec![];
+                }
+                if !self.connected(i, block_producer_ix) {
+                    return vec![];
                 }
-                let votes = n.votes();
-                Some((i, votes))
+                let mut votes = vec![(i, n.votes())];
+                if let Some(conflicting) = n.equivocation_votes(&self.banks) {
+                    votes.push((i, conflicting));
+                }
+                votes
             })
             .collect();
-        let block = block_producer.make_block(self.slot, votes);
-        self.banks.apply(&block);
-        self.nodes.iter_mut().enumerate().for_each(|(i, n)| {
-            if Self::check_same_partition(self.num_partitions, block_producer_ix, i) {
-                n.set_active_block(self.slot);
+        let mut block = block_producer.make_block_with_policy(
+            self.slot,
+            votes,
+            &self.config.inclusion_policy,
+            &self.banks,
+        );
+        if let Some(mutator) = &mut self.block_mutator {
+            mutator(&mut block);
+        }
+        let old_root = self.root();
+        if let Err(e) = self.banks.apply(&block) {
+            //the block was rejected, this slot is skipped
+            self.apply_errors.push((self.slot, e));
+            self.deliver();
+            return None;
+        }
+        self.last_step_votes = self.banks.applied_votes();
+        self.total_votes += self.last_step_votes;
+        let root = self.root();
+        if root.slot > old_root.slot {
+            for callback in &mut self.root_callbacks {
+                callback(root);
             }
-        });
+        }
+        if let Some(recording) = &mut self.recording {
+            recording.push(block.clone());
+        }
+        //the producer sees its block right away, everyone else after the delay
+        self.nodes[block_producer_ix].set_active_block(block.slot);
+        self.in_flight
+            .entry(self.slot + self.delay_slots as Slot)
+            .or_default()
+            .push((block_producer_ix, block.slot));
+        self.deliver();
         if self.num_partitions > 0 {
             self.partitioned_blocks
                 .push_back((block_producer_ix, block.slot));
         }
         let root_slot = self.root().slot;
         self.partitioned_blocks.retain(|(_, b)| *b >= root_slot);
+        self.last_block = Some(block);
+        Some(block_producer_ix)
+    }
+}
+
+#[test]
+fn test_reset() {
+    let mut network = Network::default();
+    network.create_partitions(2);
+    for _ in 0..8 {
+        network.step();
+    }
+    network.reset();
+    let fresh = Network::default();
+    assert_eq!(network.root(), fresh.root());
+    assert_eq!(network.switch_stats(), SwitchStats::default());
+    assert_eq!(network.tower_height_histogram(), vec![(0, NUM_NODES)]);
+    assert_eq!(network.root_spread(), (0, 0));
+    assert_eq!(network.snapshot().to_json(), fresh.snapshot().to_json());
+}
+
+#[test]
+fn test_checkpoint() {
+    let mut network = Network::default();
+    for _ in 0..8 {
+        network.step();
+    }
+    network.create_partitions(2);
+    network.set_delay(2);
+    for _ in 0..4 {
+        network.step();
+    }
+    let path = std::env::temp_dir().join(format!("tower_sim_{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+    network.save_checkpoint(path).unwrap();
+    let mut loaded = Network::load_checkpoint(path).unwrap();
+    fs::remove_file(path).unwrap();
+    assert_eq!(loaded.snapshot().to_json(), network.snapshot().to_json());
+    for _ in 0..8 {
+        network.step();
+        loaded.step();
+    }
+    assert_eq!(loaded.snapshot().to_json(), network.snapshot().to_json());
+}
+
+#[test]
+fn test_fork_choice_snapshot() {
+    use crate::fork_choice::LowestSlotTieBreak;
+    use std::collections::HashMap;
+    struct Tip;
+    impl ForkChoice for Tip {
+        fn choose(&self, weights: &HashMap<Slot, usize>, _banks: &Banks) -> Slot {
+            *weights.keys().max().unwrap_or(&0)
+        }
+    }
+    let mut network = Network::default();
+    network.set_fork_choice(0, Box::new(LowestSlotTieBreak));
+    network.step_n(4);
+    let json = network.snapshot().to_json();
+    let loaded = Network::from_snapshot(NetworkSnapshot::from_json(&json).unwrap()).unwrap();
+    assert_eq!(loaded.nodes[0].fork_choice.name(), "lowest_slot_tie_break");
+    assert_eq!(loaded.nodes[1].fork_choice.name(), "heaviest_fork");
+    assert_eq!(loaded.switch_stats(), network.switch_stats());
+    assert!(network.wasted_vote_slots() > 0);
+    assert_eq!(loaded.wasted_vote_slots(), network.wasted_vote_slots());
+    //custom rules survive undo but can't be loaded from a file
+    network.set_fork_choice(1, Box::new(Tip));
+    network.step();
+    let json = network.snapshot().to_json();
+    assert!(NetworkSnapshot::from_json(&json).is_err());
+    network.undo_step().unwrap();
+    assert_eq!(network.nodes[0].fork_choice.name(), "lowest_slot_tie_break");
+    assert_eq!(network.nodes[1].fork_choice.name(), "custom");
+}
+
+#[test]
+fn test_invalid_fork_choice() {
+    use std::collections::HashMap;
+    //picks a slot that never gets a bank
+    struct Unknown;
+    impl ForkChoice for Unknown {
+        fn choose(&self, _weights: &HashMap<Slot, usize>, _banks: &Banks) -> Slot {
+            Slot::MAX
+        }
+    }
+    let mut network = Network::default();
+    network.step_n(4);
+    let fork = network.nodes[0].heaviest_fork.clone();
+    network.set_fork_choice(0, Box::new(Unknown));
+    network.drain_rejections();
+    network.step_n(4);
+    let rejected = VoteRejection {
+        id: 0,
+        slot: Slot::MAX,
+        reason: RejectReason::ForkChoice,
+    };
+    let rejections: Vec<_> = network
+        .drain_rejections()
+        .into_iter()
+        .filter(|r| r.id == 0)
+        .collect();
+    assert_eq!(rejections, vec![rejected; 4]);
+    //the node keeps its last fork and the rest keep rooting, its own
+    //root holds lowest_root back
+    assert_eq!(network.nodes[0].heaviest_fork, fork);
+    network.step_n(32);
+    assert!(network.super_root() > 0);
+}
+
+#[test]
+fn test_replay() {
+    let mut network = Network::default();
+    network.start_recording();
+    for _ in 0..8 {
+        network.step();
+    }
+    let json = crate::snapshot::blocks_to_json(&network.take_recording());
+    let blocks = crate::snapshot::blocks_from_json(&json).unwrap();
+    assert_eq!(blocks.len(), 8);
+    let mut banks = Banks::default();
+    let roots = banks.replay(&blocks).unwrap();
+    assert_eq!(roots.last(), Some(&network.banks.lowest_root));
+    assert_eq!(
+        serde_json::to_string(&banks.snapshot()).unwrap(),
+        serde_json::to_string(&network.banks.snapshot()).unwrap()
+    );
+}
+
+#[test]
+fn test_asymmetric_partition() {
+    let mut network = Network::default();
+    network.create_asymmetric_partition(true, false);
+    //partition a is the even ids, b the odd ones
+    assert!(network.connected(1, 0));
+    assert!(!network.connected(0, 1));
+    assert!(network.connected(0, 2));
+    for _ in 0..4 {
+        network.step();
+    }
+    let json = network.snapshot().to_json();
+    let loaded = Network::from_snapshot(NetworkSnapshot::from_json(&json).unwrap()).unwrap();
+    assert!(loaded.connected(1, 0));
+    assert!(!loaded.connected(0, 1));
+    assert_eq!(network.partition_of(3), Some(1));
+    network.repair_partitions(0);
+    assert!(network.connected(0, 1));
+    assert_eq!(network.partition_of(3), None);
+}
+
+#[test]
+fn test_partitions() {
+    let mut network = Network::default();
+    assert_eq!(
+        network.partitions(),
+        vec![(0..NUM_NODES).collect::<Vec<_>>()]
+    );
+    network.create_partitions(3);
+    assert_eq!(network.partition_of(4), Some(1));
+    assert_eq!(network.partitions()[2][..2], [2, 5]);
+    network.create_partitions_weighted(&[0.5, 0.5]).unwrap();
+    let partitions = network.partitions();
+    assert_eq!(partitions.len(), 2);
+    assert_eq!(partitions[0].len() + partitions[1].len(), NUM_NODES);
+    assert_eq!(network.partition_of(NUM_NODES - 1), Some(1));
+}
+
+#[test]
+fn test_incremental_fork_weights() {
+    let mut network = Network::default();
+    network.set_byzantine(&[1, 2]);
+    network.halt_node(3);
+    for slot in 0..96 {
+        if slot == 16 {
+            network.create_partitions(2);
+        }
+        if slot == 48 {
+            network.repair_partitions(0);
+        }
+        network.step();
+        assert_eq!(
+            network.banks.fork_weights,
+            network.banks.rebuilt_fork_weights()
+        );
+        assert_eq!(network.banks.verify_tree(), Ok(()));
+    }
+}
+
+#[test]
+fn test_retain_depth() {
+    let mut network = Network::with_config(Config {
+        retain_depth: 4,
+        ..Config::default()
+    });
+    while network.root().slot < 8 {
+        network.step();
+    }
+    let root = network.root().slot;
+    let banks = &network.banks;
+    assert!(!banks.fork_map.contains_key(&(root - 1)));
+    assert_eq!(banks.historical_bank(root - 1).unwrap().slot, root - 1);
+    assert!(banks.historical_bank(root - 5).is_none());
+    assert!(banks.historical_bank(root).is_some());
+    let json = network.snapshot().to_json();
+    let loaded = Network::from_snapshot(NetworkSnapshot::from_json(&json).unwrap()).unwrap();
+    assert!(loaded.banks.historical_bank(root - 1).is_some());
+}
+
+#[test]
+fn test_slot_offset() {
+    let mut network = Network::default();
+    network.set_slot_offset(0, -4);
+    network.set_slot_offset(1, -100);
+    assert_eq!(network.nodes[1].local_slot(50), 0);
+    for _ in 0..8 {
+        network.step();
+        //the lagging node votes at most on the block 4 slots back
+        if let Some(v) = network.nodes[0].tower().latest_vote() {
+            assert!(v.slot + 4 <= network.slot);
+        }
+    }
+    assert!(network.nodes[1].tower().latest_vote().is_none());
+}
+
+#[test]
+fn test_on_root_advance() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    let mut network = Network::default();
+    let calls = Rc::new(RefCell::new(vec![]));
+    for i in 0..2 {
+        let calls = calls.clone();
+        network.on_root_advance(Box::new(move |root| calls.borrow_mut().push((i, root))));
+    }
+    while network.root().slot == 0 {
+        network.step();
+    }
+    let root = network.root();
+    assert_eq!(*calls.borrow(), vec![(0, root), (1, root)]);
+}
+
+#[test]
+fn test_non_leader() {
+    //round robin by default
+    let network = Network::default();
+    assert_eq!(network.leader_for(5), 5);
+    assert_eq!(network.leader_for(NUM_NODES as Slot + 5), 5);
+    let mut network = Network::with_leader_schedule(LeaderSchedule::Fixed(vec![1, 2]));
+    network.set_non_leader(&[1]);
+    assert_eq!(network.leader_for(0), 2);
+    assert_eq!(network.leader_for(1), 2);
+    network.start_recording();
+    for _ in 0..4 {
+        network.step();
+    }
+    let blocks = network.take_recording();
+    assert_eq!(blocks.len(), 4);
+    //node 1's votes still make it into node 2's blocks
+    assert!(blocks
+        .iter()
+        .any(|b| b.votes.iter().any(|(id, _)| *id == 1)));
+    network.set_non_leader(&[1, 2]);
+    network.step();
+    assert!(network.take_recording().is_empty());
+}
+
+#[test]
+fn test_undo_step() {
+    let mut network = Network::default();
+    assert_eq!(network.undo_step(), Err(UndoError::NoHistory));
+    network.set_undo_depth(2);
+    for _ in 0..4 {
+        network.step();
+    }
+    //the counters come back too, not just the snapshot state
+    let state = |network: &Network| {
+        (
+            network.snapshot().to_json(),
+            network.total_votes(),
+            network.last_step_votes(),
+            network.rejections.clone(),
+            network.heaviest_tip,
+        )
+    };
+    let before = state(&network);
+    network.step();
+    network.step();
+    network.undo_step().unwrap();
+    network.undo_step().unwrap();
+    assert_eq!(network.undo_step(), Err(UndoError::NoHistory));
+    assert_eq!(state(&network), before);
+    network.step();
+    network.undo_step().unwrap();
+    assert_eq!(state(&network), before);
+}
+
+#[test]
+fn test_vote_throughput() {
+    let mut network = Network::default();
+    let mut total = 0;
+    for _ in 0..4 {
+        network.step();
+        total += network.last_step_votes();
+    }
+    assert_eq!(network.total_votes(), total);
+    //every node sends its whole tower but only its new vote is applied
+    assert_eq!(network.last_step_votes(), NUM_NODES);
+    //only the producer's partition gets its votes in
+    network.create_partitions(2);
+    network.step();
+    assert!(network.last_step_votes() > 0);
+    assert!(network.last_step_votes() <= NUM_NODES / 2 + 1);
+    network.reset();
+    assert_eq!(network.total_votes(), 0);
+}
+
+#[test]
+fn test_skipped_slots() {
+    let mut network = Network::with_config(Config {
+        skip_slots: vec![3, 4, 9],
+        ..Config::default()
+    });
+    for _ in 0..12 {
+        network.step();
+    }
+    assert!(!network.banks.fork_map.contains_key(&3));
+    assert_eq!(network.banks.fork_map[&5].parent, 2);
+    assert_eq!(network.banks.compute_fork(10)[..4], [10, 8, 7, 6]);
+    network.banks.verify_tree().unwrap();
+
+    //roots keep advancing across random gaps
+    let mut network = Network::with_skip_rate(0.3);
+    let mut skipped = 0;
+    for slot in 1..=64 {
+        network.step();
+        if network.is_skipped(slot) {
+            skipped += 1;
+            assert!(!network.banks.fork_map.contains_key(&slot));
+        }
+    }
+    assert!(skipped > 5 && skipped < 40);
+    assert!(network.root().slot > 16);
+    network.banks.verify_tree().unwrap();
+    let snapshot = network.snapshot();
+    let loaded =
+        Network::from_snapshot(NetworkSnapshot::from_json(&snapshot.to_json()).unwrap()).unwrap();
+    assert_eq!(loaded.config, network.config);
+}
+
+#[test]
+fn test_event_stream() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    let out = Shared::default();
+    let mut network = Network::with_config(Config {
+        skip_slots: vec![2],
+        ..Config::default()
+    });
+    network.enable_event_stream(Box::new(out.clone()));
+    for _ in 0..3 {
+        network.step();
+    }
+    let text = String::from_utf8(out.0.borrow().clone()).unwrap();
+    let events: Vec<StepEvent> = text
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0].slot, 1);
+    assert_eq!(events[0].leader, Some(network.leader_for(1)));
+    assert_eq!(events[0].root, Vote::zero());
+    assert_eq!(events[0].fork_count, 1);
+    //every node's root is already slot 0 on the first step
+    assert_eq!(events[0].rejections.len(), NUM_NODES);
+    assert!(events[0]
+        .rejections
+        .iter()
+        .all(|r| r.slot == 0 && r.reason == "tower"));
+    assert_eq!(events[0].rejections[0].id, 0);
+    //slot 2 is skipped
+    assert_eq!(events[1].slot, 2);
+    assert_eq!(events[1].leader, None);
+    assert_eq!(events[2].slot, 3);
+}
+
+#[test]
+fn test_participation() {
+    let config = Config {
+        participation: 0.9,
+        ..Config::default()
+    };
+    let mut a = Network::with_config(config.clone());
+    let mut b = Network::with_config(config);
+    let mut full = Network::default();
+    for _ in 0..64 {
+        a.step();
+        b.step();
+        full.step();
+    }
+    //the same seed picks the same voters
+    assert_eq!(a.snapshot().to_json(), b.snapshot().to_json());
+    assert!(a.root().slot > 0 && a.root().slot <= full.root().slot);
+    let voters = (0..NUM_NODES).filter(|id| a.participates(*id, 5)).count();
+    assert!(voters > NUM_NODES * 8 / 10 && voters < NUM_NODES);
+    assert!((0..NUM_NODES).all(|id| full.participates(id, 5)));
+}
+
+#[test]
+fn test_block_gc_threshold() {
+    let mut network = Network::default();
+    let mut small = Network::default();
+    small.set_block_gc_threshold(8);
+    for _ in 0..48 {
+        network.step();
+        small.step();
+    }
+    assert!(small.root().slot > 0);
+    assert_eq!(small.root(), network.root());
+    //gc runs on every new block once there are 8, keeping those at or
+    //above the root
+    for n in small.snapshot().nodes {
+        assert!(n.blocks.iter().all(|b| *b >= n.tower.root.slot));
+        assert_eq!(n.block_gc_threshold, 8);
+    }
+    assert!(network.snapshot().nodes[0].blocks.contains(&0));
+}
+
+#[test]
+fn test_set_initial_tower() {
+    let mut network = Network::with_config(Config {
+        skip_slots: vec![2],
+        ..Config::default()
+    });
+    let mut tower = Tower::default();
+    tower.root.slot = 1;
+    assert_eq!(
+        network.set_initial_tower(0, tower.clone()),
+        Err(InitialTowerError::Root(tower.root))
+    );
+    let mut tower = Tower::default();
+    tower.votes.push_front(Vote {
+        slot: 2,
+        lockout: 2,
+    });
+    tower.votes.push_front(Vote {
+        slot: 3,
+        lockout: 4,
+    });
+    assert_eq!(
+        network.set_initial_tower(0, tower),
+        Err(InitialTowerError::Tower(TowerError::NonMonotonic))
+    );
+    //a quarter of the nodes start locked out on slot 2, which is never
+    //produced
+    let mut tower = Tower::default();
+    tower.votes.push_front(Vote {
+        slot: 2,
+        lockout: 8,
+    });
+    for id in 0..NUM_NODES / 4 {
+        network.set_initial_tower(id, tower.clone()).unwrap();
+    }
+    for _ in 0..6 {
+        network.step();
+    }
+    assert!(network.drain_rejections().iter().any(|r| r.id == 0
+        && r.reason
+            == RejectReason::Lockout(Vote {
+                slot: 2,
+                lockout: 8
+            })));
+    assert_eq!(
+        network.set_initial_tower(0, Tower::default()),
+        Err(InitialTowerError::NotGenesis(6))
+    );
+    //the rest are 2/3+ of the nodes and root without them
+    for _ in 0..42 {
+        network.step();
+    }
+    assert!(network.node_root(NUM_NODES - 1).slot > 0);
+}
+
+#[test]
+fn test_fault_tolerance() {
+    let mut network = Network::default();
+    assert_eq!(network.fault_tolerance(), NUM_NODES / 3);
+    for id in 0..10 {
+        network.halt_node(id);
+    }
+    assert_eq!(network.fault_tolerance(), NUM_NODES / 3 - 10);
+    for id in 0..NUM_NODES / 3 + 1 {
+        network.halt_node(id);
+    }
+    assert_eq!(network.fault_tolerance(), 0);
+}
+
+#[test]
+fn test_supermajority() {
+    use crate::bank::{minority_nodes, supermajority_nodes};
+    assert_eq!(supermajority_nodes((2, 3)), (2 * NUM_NODES) / 3);
+    assert_eq!(minority_nodes((2, 3)), NUM_NODES / 3);
+    let config = Config {
+        supermajority: (3, 4),
+        ..Config::default()
+    };
+    let mut strict = Network::with_config(config.clone());
+    let mut network = Network::default();
+    assert_eq!(
+        strict.fault_tolerance(),
+        NUM_NODES - (3 * NUM_NODES) / 4 - 1
+    );
+    //700 nodes online are 2/3+ but not 3/4+. the halted nodes are the
+    //last ones so the round robin leaders stay online
+    for id in NUM_NODES - 300..NUM_NODES {
+        strict.halt_node(id);
+        network.halt_node(id);
+    }
+    for _ in 0..48 {
+        strict.step();
+        network.step();
+    }
+    assert!(network.node_root(0).slot > 0);
+    assert_eq!(strict.node_root(0).slot, 0);
+    let snapshot = strict.snapshot();
+    let loaded =
+        Network::from_snapshot(NetworkSnapshot::from_json(&snapshot.to_json()).unwrap()).unwrap();
+    assert_eq!(loaded.config, config);
+    assert_eq!(loaded.nodes[0].supermajority, (3, 4));
+    assert_eq!(
+        loaded.banks.fork_map[&loaded.root().slot].supermajority,
+        (3, 4)
+    );
+}
+
+#[test]
+fn test_convergence() {
+    let mut network = Network::default();
+    network.step();
+    assert!(network.is_converged());
+    network.create_partitions(2);
+    for _ in 0..8 {
+        network.step();
+    }
+    assert!(!network.is_converged());
+    assert_eq!(network.steps_to_convergence(), None);
+    network.repair_partitions(0);
+    assert_eq!(network.steps_to_convergence(), None);
+    network.step();
+    assert!(network.is_converged());
+    assert_eq!(network.steps_to_convergence(), Some(1));
+    network.step();
+    assert_eq!(network.steps_to_convergence(), Some(1));
+    network.reset();
+    assert_eq!(network.steps_to_convergence(), None);
+}
+
+#[test]
+fn test_leader_failure_rate() {
+    let mut network = Network::with_leader_failure_rate(0.3);
+    let mut full = Network::default();
+    let mut failed = 0;
+    for slot in 1..=64 {
+        network.step();
+        full.step();
+        if network.leader_fails(slot) {
+            failed += 1;
+            assert!(!network.banks.fork_map.contains_key(&slot));
+        }
+        assert!(!full.leader_fails(slot));
+    }
+    assert!(failed > 5 && failed < 40);
+    //failed leaders slow rooting down but don't stop it
+    assert!(network.root().slot > 0 && network.root().slot < full.root().slot);
+    network.banks.verify_tree().unwrap();
+    let loaded =
+        Network::from_snapshot(NetworkSnapshot::from_json(&network.snapshot().to_json()).unwrap())
+            .unwrap();
+    assert_eq!(loaded.config.leader_failure_rate, 0.3);
+}
+
+#[test]
+fn test_last_block() {
+    let mut network = Network::with_config(Config {
+        skip_slots: vec![3],
+        ..Config::default()
+    });
+    assert!(network.last_block().is_none());
+    network.step();
+    network.step();
+    let block = network.last_block().unwrap();
+    assert_eq!((block.slot, block.parent), (2, 1));
+    assert_eq!(block.votes.len(), NUM_NODES);
+    assert!(block.votes.iter().all(|(_, v)| v.last().unwrap().slot == 1));
+    //the skipped slot keeps the block from slot 2
+    network.step();
+    assert_eq!(network.last_block().unwrap().slot, 2);
+    network.reset();
+    assert!(network.last_block().is_none());
+}
+
+#[test]
+fn test_diff() {
+    let mut a = Network::default();
+    let mut b = Network::default();
+    a.step();
+    assert_eq!(a.diff(&b), Err(DiffError::SlotMismatch(1, 0)));
+    b.step();
+    assert_eq!(a.diff(&b), Ok(RunDiff::default()));
+    //b can't root without 2/3+ of the nodes
+    for id in 0..400 {
+        b.halt_node(id);
+    }
+    for _ in 0..40 {
+        a.step();
+        b.step();
+    }
+    let root = a.root().slot;
+    assert!(root > 0);
+    let diff = a.diff(&b).unwrap();
+    assert_eq!(diff.root, -(root as i64));
+    assert_eq!(diff.differing_roots, NUM_NODES);
+    assert!(diff.max_root_gap >= root);
+    assert_eq!(b.diff(&a).unwrap().root, root as i64);
+}
+
+#[test]
+fn test_catch_up() {
+    let mut network = Network::with_config(Config {
+        skip_slots: vec![2],
+        ..Config::default()
+    });
+    network.step_n(24);
+    //node 7 holds lowest_root back below the supermajority root
+    network.halt_node(7);
+    let behind = network.node_root(7);
+    network.step_n(40);
+    assert_eq!(network.root(), behind);
+    let super_root = network.super_root();
+    assert!(super_root > network.root().slot);
+    //a halted node is what holds lowest_root back, it stays put
+    network.resume_node(7);
+    network.catch_up(7).unwrap();
+    assert_eq!(network.node_root(7), behind);
+    //node 5 restarts with a tower locked out on slot 2, which is never
+    //produced
+    let mut tower = Tower::default();
+    tower.votes.push_front(Vote {
+        slot: 2,
+        lockout: 1 << 12,
+    });
+    network.nodes[5].set_tower(tower);
+    assert_eq!(
+        network.catch_up(5),
+        Err(CatchUpError::LockedOut(Vote {
+            slot: 2,
+            lockout: 1 << 12
+        }))
+    );
+    //without its tower it adopts lowest_root, not the supermajority root
+    network.nodes[5].set_tower(Tower::default());
+    network.catch_up(5).unwrap();
+    let root = network.node_root(5);
+    assert_eq!(root, network.root());
+    assert!(root.slot < super_root);
+    assert!(network.nodes[5].tower().votes.is_empty());
+    assert_eq!(network.check_invariants(), Ok(()));
+}
+
+#[test]
+fn test_wasted_vote_slots() {
+    let mut network = Network::default();
+    let mut rejected = 0;
+    for _ in 0..4 {
+        network.step();
+        rejected += network.drain_rejections().len();
+    }
+    assert_eq!(network.wasted_vote_slots(), rejected);
+    //every node's first vote is on its root
+    assert_eq!(rejected, NUM_NODES);
+    network.create_partitions(2);
+    for _ in 0..16 {
+        network.step();
+        rejected += network.drain_rejections().len();
+    }
+    assert_eq!(network.wasted_vote_slots(), rejected);
+    //neither half has 2/3+ of the nodes, the threshold check fails
+    assert!(network.wasted_vote_slots() > NUM_NODES);
+    network.reset();
+    assert_eq!(network.wasted_vote_slots(), 0);
+}
+
+#[test]
+fn test_max_orphan_depth() {
+    let mut network = Network::default();
+    for _ in 0..32 {
+        network.step();
+    }
+    //a single fork never orphans anything
+    assert_eq!(network.max_orphan_depth(), 0);
+    network.create_partitions(2);
+    for _ in 0..8 {
+        network.step();
+    }
+    network.repair_partitions(0);
+    for _ in 0..64 {
+        network.step();
+    }
+    let depth = network.max_orphan_depth();
+    //the losing side of the split was pruned once the root moved past it
+    assert!(depth > 0 && depth <= 8, "{depth}");
+}
+
+#[test]
+fn test_force_vote() {
+    let mut network = Network::default();
+    for _ in 0..8 {
+        network.step();
+    }
+    network.create_partitions(2);
+    for _ in 0..8 {
+        network.step();
+    }
+    let tip = |network: &Network, id: ID| *network.nodes[id].heaviest_fork.first().unwrap();
+    let partitions = network.partitions();
+    let (a, b) = (partitions[0][0], partitions[1][0]);
+    let other = tip(&network, b);
+    let last = network.nodes[a].tower().votes.front().copied().unwrap();
+    let wasted = network.nodes[a].wasted_vote_slots();
+    //the rejected votes below still point heaviest_fork at their slot
+    let own = network.nodes[a].heaviest_fork[1];
+    //the other side's tip conflicts with a's recent votes
+    let rejection = network.nodes[a]
+        .force_vote(other, &network.banks)
+        .unwrap_err();
+    assert_eq!((rejection.id, rejection.slot), (a, other));
+    assert!(
+        matches!(rejection.reason, RejectReason::Lockout(v) if v.slot <= last.slot),
+        "{rejection:?}"
+    );
+    //older slots on its own fork can't be voted on again
+    assert_eq!(
+        network.nodes[a].force_vote(own, &network.banks),
+        Err(VoteRejection {
+            id: a,
+            slot: own,
+            reason: RejectReason::Tower(TowerError::NonMonotonic),
+        })
+    );
+    //rejected votes leave the tower alone
+    assert_eq!(network.nodes[a].tower().votes.front(), Some(&last));
+    assert_eq!(network.nodes[a].wasted_vote_slots(), wasted + 2);
+    //votes that would cast nothing are rejected too
+    let reason = |network: &mut Network, slot: Slot| {
+        network.nodes[a]
+            .force_vote(slot, &network.banks)
+            .unwrap_err()
+            .reason
+    };
+    assert_eq!(
+        reason(&mut network, last.slot),
+        RejectReason::Tower(TowerError::AlreadyVoted)
+    );
+    assert_eq!(reason(&mut network, Slot::MAX), RejectReason::MissingBank);
+    network.halt_node(a);
+    assert_eq!(reason(&mut network, other), RejectReason::Halted);
+    assert_eq!(network.nodes[a].tower().votes.front(), Some(&last));
+}
+
+#[test]
+fn test_threshold_oscillations() {
+    let mut network = Network::default();
+    for _ in 0..8 {
+        network.step();
+    }
+    assert!((0..NUM_NODES).all(|id| network.threshold_oscillations(id) == 0));
+    //neither half has 2/3+ of the nodes, so deep votes start failing
+    network.create_partitions(2);
+    for _ in 0..16 {
+        network.step();
+    }
+    let flipped: Vec<_> = (0..NUM_NODES)
+        .filter(|id| network.threshold_oscillations(*id) > 0)
+        .collect();
+    assert!(!flipped.is_empty());
+    //and pass again once the network is whole
+    network.repair_partitions(0);
+    for _ in 0..32 {
+        network.step();
+    }
+    assert!(flipped
+        .iter()
+        .all(|id| network.threshold_oscillations(*id) >= 2));
+}
+
+#[test]
+fn test_step_n() {
+    let mut network = Network::default();
+    let mut expected = Network::default();
+    let mut root_advances = 0;
+    for _ in 0..48 {
+        let root = expected.root();
+        expected.step();
+        if expected.root() != root {
+            root_advances += 1;
+        }
+    }
+    let summary = network.step_n(48);
+    assert_eq!(
+        summary,
+        StepSummary {
+            root_advances,
+            votes: expected.total_votes(),
+            fork_count: expected.fork_count(),
+            violations: vec![],
+        }
+    );
+    assert!(summary.root_advances > 0);
+    assert_eq!(network.root(), expected.root());
+    assert_eq!(network.step_n(0).votes, 0);
+}
+
+#[test]
+fn test_set_node_threshold() {
+    let mut network = Network::default();
+    network.step_n(8);
+    //half the nodes never threshold check their votes
+    let (old, new): (Vec<ID>, Vec<ID>) = (0..NUM_NODES).partition(|id| id % 2 == 0);
+    for id in &old {
+        network.set_node_threshold(*id, DEPTH);
+    }
+    network.create_partitions(2);
+    let summary = network.step_n(16);
+    let rejections = network.drain_rejections();
+    let threshold = |ids: &[ID]| {
+        rejections
+            .iter()
+            .filter(|r| ids.contains(&r.id) && matches!(r.reason, RejectReason::Threshold(_)))
+            .count()
+    };
+    assert_eq!(threshold(&old), 0);
+    assert!(threshold(&new) > 0);
+    network.repair_partitions(0);
+    let after = network.step_n(64);
+    //roots still need 2/3+ of the nodes, so the split can't root both sides
+    assert_eq!(summary.violations, vec![]);
+    assert_eq!(after.violations, vec![]);
+    assert!(after.root_advances > 0);
+}
+
+#[test]
+fn test_super_root_gap() {
+    let mut network = Network::default();
+    network.step_n(48);
+    assert!(network.super_root() > 0);
+    assert_eq!(network.super_root_gap(), 0);
+    //the halted node's root holds the lowest root back
+    network.halt_node(7);
+    network.step_n(32);
+    assert_eq!(network.root(), network.node_root(7));
+    assert!(network.super_root() >= network.node_root(NUM_NODES - 1).slot);
+    assert_eq!(
+        network.super_root_gap(),
+        network.super_root() - network.root().slot
+    );
+    assert!(network.super_root_gap() > 0);
+    //banks only move the node's root once its new votes get deep enough
+    network.resume_node(7);
+    network.catch_up(7).unwrap();
+    network.step_n(64);
+    assert!(
+        network.super_root_gap() < DEPTH as Slot,
+        "{}",
+        network.super_root_gap()
+    );
+}
+
+#[test]
+fn test_inclusion_policy() {
+    let mut network = Network::default();
+    network.step_n(48);
+    let mut uncapped = Network::with_inclusion_policy(InclusionPolicy::Cap(NUM_NODES));
+    uncapped.step_n(48);
+    assert_eq!(uncapped.root(), network.root());
+    //votes arrive in id order, so the same nodes always make it in
+    let quorum = crate::bank::supermajority_nodes(crate::bank::SUPERMAJORITY) + 1;
+    let mut capped = Network::with_inclusion_policy(InclusionPolicy::Cap(quorum));
+    capped.step_n(48);
+    assert!(capped.super_root() > 0);
+    assert_eq!(capped.root().slot, 0);
+    let mut capped = Network::with_inclusion_policy(InclusionPolicy::Cap(quorum - 1));
+    capped.step_n(48);
+    assert_eq!(capped.super_root(), 0);
+    //latest votes are on the parent, a window of 0 drops every vote
+    let mut stale = Network::with_inclusion_policy(InclusionPolicy::Window(0));
+    stale.step_n(48);
+    assert_eq!(stale.total_votes(), 0);
+}
+
+#[test]
+fn test_reorg_events() {
+    let mut network = Network::default();
+    network.step_n(24);
+    assert_eq!(network.reorg_events(), vec![]);
+    network.create_partitions(2);
+    network.step_n(16);
+    network.repair_partitions(0);
+    network.step_n(32);
+    //each side's newest block briefly wins the tie for heaviest
+    let reorgs = network.reorg_events();
+    assert!(!reorgs.is_empty());
+    assert!(reorgs.iter().all(|(old, new)| *old > 24 && new > old));
+    //undo drops the reorg of the undone step
+    network.create_partitions(2);
+    while network.reorg_events().len() == reorgs.len() {
+        network.step();
+    }
+    network.undo_step().unwrap();
+    assert_eq!(network.reorg_events(), reorgs);
+    network.reset();
+    assert_eq!(network.reorg_events(), vec![]);
+}
+
+#[test]
+fn test_permanent_partition() {
+    let mut network = Network::default();
+    network.set_undo_depth(0);
+    network.step_n(32);
+    assert!(!network.partition_stalled());
+    network.create_partitions(3);
+    assert!(network.partition_stalled());
+    let root = network.root();
+    //never repaired, every side keeps voting on its own fork
+    let summary = network.step_n(256);
+    assert_eq!(summary.violations, vec![]);
+    assert_eq!(summary.root_advances, 0);
+    assert_eq!(network.root(), root);
+    assert_eq!(network.fork_count(), 3);
+    assert!(matches!(
+        network.root_stall_reason(),
+        StallReason::NoSupermajority { .. }
+    ));
+    //a side with a supermajority keeps rooting, the other holds
+    //lowest_root back
+    let mut network = Network::default();
+    network.set_undo_depth(0);
+    network.step_n(32);
+    let (root, super_root) = (network.root(), network.super_root());
+    network.create_partitions_weighted(&[0.7, 0.3]).unwrap();
+    assert!(!network.partition_stalled());
+    assert_eq!(network.step_n(64).violations, vec![]);
+    assert_eq!(network.root(), root);
+    assert!(network.super_root() > super_root);
+}
+
+#[test]
+fn test_block_mutator() {
+    let mut network = Network::default();
+    network.step_n(16);
+    let root = network.root();
+    //dropped votes still make valid blocks, but nothing roots
+    network.set_block_mutator(Box::new(|block| block.votes.clear()));
+    let summary = network.step_n(40);
+    assert_eq!((summary.votes, summary.root_advances), (0, 0));
+    assert_eq!(network.root(), root);
+    assert!(network.apply_errors().is_empty());
+    //invalid blocks are skipped and reported
+    network.set_block_mutator(Box::new(|block| block.parent = block.slot));
+    network.step();
+    network.set_block_mutator(Box::new(|block| block.votes.push((NUM_NODES, vec![]))));
+    network.step();
+    network.set_block_mutator(Box::new(|block| {
+        let (id, votes) = &mut block.votes[0];
+        votes[0].lockout = 1 << 3;
+        *id = 3;
+    }));
+    network.step();
+    let slot = network.slot;
+    assert_eq!(
+        network.apply_errors()[..2],
+        [
+            (
+                slot - 2,
+                ApplyError::NotAfterParent {
+                    slot: slot - 2,
+                    parent: slot - 2
+                }
+            ),
+            (slot - 1, ApplyError::UnknownNode(NUM_NODES)),
+        ]
+    );
+    assert!(matches!(
+        network.apply_errors()[2],
+        (s, ApplyError::InvalidLockout { id: 3, vote }) if s == slot && vote.lockout == 1 << 3
+    ));
+    //the skipped slots have no banks
+    assert!(network.banks.bank(slot).is_none());
+    network.undo_step().unwrap();
+    assert_eq!(network.apply_errors().len(), 2);
+    network.reset();
+    assert!(network.apply_errors().is_empty());
+    assert_eq!(network.step_n(16).votes, network.total_votes());
+}
+
+#[test]
+fn test_rooted_chain_length() {
+    let mut network = Network::with_config(Config {
+        skip_slots: vec![3, 5, 6],
+        ..Config::default()
+    });
+    assert_eq!(network.banks.rooted_chain_length(), 1);
+    network.step_n(64);
+    //every slot up to the root except the skipped ones
+    let root = network.root().slot;
+    assert!(root > 6);
+    assert_eq!(network.banks.rooted_chain_length(), root as usize + 1 - 3);
+    let snapshot = network.snapshot();
+    let loaded = Network::from_snapshot(snapshot).unwrap();
+    assert_eq!(loaded.banks.rooted_chain_length(), root as usize - 2);
+}
+
+#[test]
+fn test_create_partitions_explicit() {
+    let mut network = Network::with_leader_schedule(LeaderSchedule::Fixed(vec![5]));
+    network.step_n(16);
+    //the only leader is in the minority
+    let minority: Vec<ID> = (0..NUM_NODES / 3).rev().collect();
+    let majority: Vec<ID> = (NUM_NODES / 3..NUM_NODES).collect();
+    let mut missing = majority.clone();
+    missing.pop();
+    let mut duplicate = majority.clone();
+    duplicate.push(3);
+    assert_eq!(
+        network.create_partitions_explicit(&[minority.clone(), missing]),
+        Err(PartitionError::MissingNode(NUM_NODES - 1))
+    );
+    assert_eq!(
+        network.create_partitions_explicit(&[minority.clone(), duplicate]),
+        Err(PartitionError::DuplicateNode(3))
+    );
+    assert_eq!(
+        network.create_partitions_explicit(&[minority.clone(), vec![NUM_NODES]]),
+        Err(PartitionError::UnknownNode(NUM_NODES))
+    );
+    assert_eq!(
+        network.create_partitions_explicit(&[minority.clone(), majority.clone(), vec![]]),
+        Err(PartitionError::EmptyPartition(2))
+    );
+    assert_eq!(network.partition_of(0), None);
+    network
+        .create_partitions_explicit(&[minority.clone(), majority.clone()])
+        .unwrap();
+    assert_eq!(network.partition_of(5), Some(0));
+    assert_eq!(network.partition_of(NUM_NODES - 1), Some(1));
+    let mut sorted = minority.clone();
+    sorted.sort_unstable();
+    assert_eq!(network.partitions(), vec![sorted, majority.clone()]);
+    //after voting on the last block from before the split, the majority
+    //never gets another one, so its towers stop growing
+    let latest = |network: &Network| -> Vec<Option<Vote>> {
+        majority
+            .iter()
+            .map(|id| network.nodes[*id].tower().votes.front().copied())
+            .collect()
+    };
+    network.step();
+    let before = latest(&network);
+    network.step_n(16);
+    assert_eq!(latest(&network), before);
+}
+
+#[test]
+fn test_vote_lag() {
+    let mut network = Network::with_config(Config::default());
+    let mut lagged = Network::with_config(Config {
+        vote_lag: 2,
+        ..Config::default()
+    });
+    network.step_n(64);
+    lagged.step_n(64);
+    //blocks still build on the tip, so rooting keeps up
+    assert!(lagged.root().slot > 16);
+    let tip = lagged.nodes[0].heaviest_fork[0];
+    let latest = lagged.nodes[0].tower().latest_vote().unwrap().slot;
+    assert!(latest < tip);
+    assert!(lagged.root().slot <= network.root().slot);
+    let loaded = Network::from_snapshot(lagged.snapshot()).unwrap();
+    assert_eq!(loaded.nodes[0].vote_lag, 2);
+}
+
+#[test]
+fn test_priority_inclusion() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    let last = NUM_NODES - 1;
+    let quorum = crate::bank::supermajority_nodes(crate::bank::SUPERMAJORITY) + 1;
+    //counts the blocks with votes, and those with the last node's tower
+    let included = |network: &mut Network| {
+        let counts = Rc::new(Cell::new((0, 0)));
+        let c = counts.clone();
+        network.set_block_mutator(Box::new(move |block| {
+            let (blocks, last_in) = c.get();
+            let has_last = block.votes.iter().any(|(id, _)| *id == last);
+            c.set((blocks + 1, last_in + has_last as usize));
+        }));
+        network.step_n(16);
+        counts.get()
+    };
+    //in arrival order the last node never makes the cap
+    let mut fifo = Network::with_inclusion_policy(InclusionPolicy::Cap(quorum));
+    let (blocks, last_in) = included(&mut fifo);
+    assert!(blocks > 0);
+    assert_eq!(last_in, 0);
+    let mut fees = vec![0; NUM_NODES];
+    fees[last] = 1;
+    let policy = InclusionPolicy::Priority { cap: quorum, fees };
+    let mut priority = Network::with_inclusion_policy(policy.clone());
+    let (blocks, last_in) = included(&mut priority);
+    //from the first vote on it's in every block
+    assert!(last_in >= blocks - 1);
+    let loaded = Network::from_snapshot(priority.snapshot()).unwrap();
+    assert_eq!(loaded.config.inclusion_policy, policy);
+}
+
+#[test]
+fn test_divergent_nodes() {
+    let mut network = Network::default();
+    network.step_n(16);
+    assert!(network.divergent_nodes().is_empty());
+    network.create_partitions(2);
+    network.step_n(8);
+    //one of the two partitions is on the minority fork
+    let divergent = network.divergent_nodes();
+    assert!(!divergent.is_empty());
+    assert!(divergent.len() <= NUM_NODES / 2 + 1);
+    network.repair_partitions(1);
+    network.step_n(4);
+    assert!(network.divergent_nodes().is_empty());
+}
+
+#[test]
+fn test_snapshot_invalid_base() {
+    let json = Network::default().snapshot().to_json();
+    assert!(json.contains("\"base\":2"));
+    for base in ["1", "16"] {
+        let json = json.replace("\"base\":2", &format!("\"base\":{}", base));
+        let err = NetworkSnapshot::from_json(&json).err().unwrap();
+        assert_eq!(err.message, format!("invalid lockout base {}", base));
+    }
+}
+
+#[test]
+fn test_equivocation_recorded() {
+    let mut network = Network::default();
+    network.set_byzantine(&[0]);
+    network.step_n(8);
+    //with one fork there's nothing to equivocate on
+    assert_eq!(network.banks.equivocations, vec![]);
+    network.create_partitions(2);
+    network.step_n(8);
+    network.repair_partitions(1);
+    network.step_n(8);
+    //node 0 votes on both forks once it sees them
+    assert!(!network.banks.equivocations.is_empty());
+    assert!(network.banks.equivocations.iter().all(|(_, id)| *id == 0));
+    assert!(network.apply_errors().is_empty());
+}
+
+#[test]
+fn test_root_latencies() {
+    let mut network = Network::default();
+    network.step_n(16);
+    network.create_partitions(2);
+    network.step_n(16);
+    let root = network.root().slot;
+    network.repair_partitions(1);
+    network.step_n(48);
+    //the repair roots several slots with one block, each gets a latency
+    let banks = &network.banks;
+    assert!(network.root().slot > root + 1);
+    assert_eq!(banks.root_latencies.len(), banks.rooted_chain_length() - 1);
+    let fork = banks.compute_fork(network.root().slot);
+    for s in fork.iter().filter(|s| **s > 0) {
+        assert!(banks.root_latencies.contains_key(s), "{} has no latency", s);
+    }
+}
+
+#[test]
+fn test_snapshot_check() {
+    let mut network = Network::default();
+    network.create_partitions(2);
+    network.step_n(4);
+    let snap = network.snapshot();
+    assert_eq!(snap.check(), Ok(()));
+    let json = snap.to_json();
+    //inconsistent files are errors, not panics
+    let broken = json.replacen("\"threshold\":6", "\"threshold\":0", 1);
+    assert!(NetworkSnapshot::from_json(&broken).is_err());
+    let mut snap = network.snapshot();
+    snap.nodes.pop();
+    assert!(snap.check().is_err());
+    assert!(Network::from_snapshot(snap).is_err());
+    let mut snap = network.snapshot();
+    snap.banks.fork_map[0].nodes.pop();
+    assert!(Banks::from_snapshot(snap.banks, &network.config).is_err());
+    let mut snap = network.snapshot();
+    snap.healed = vec![NUM_NODES];
+    assert!(snap.check().is_err());
+    let mut snap = network.snapshot();
+    snap.partition_map = vec![0; 3];
+    assert!(snap.check().is_err());
+    let mut snap = network.snapshot();
+    snap.banks.fork_map.retain(|b| b.slot != 2);
+    assert_eq!(
+        snap.check().err().unwrap().message,
+        "child 2 of 0 is missing"
+    );
+}
+
+#[test]
+fn test_subcommittee_hash_snapshot() {
+    fn xorshift(mut x: u64) -> u64 {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^ (x << 17)
+    }
+    let config = |subcommittee_hash| Config {
+        subcommittee_hash,
+        ..Config::default()
+    };
+    //splitmix64 is restored by name
+    let mut network = Network::with_config(config(crate::bank::splitmix64));
+    network.step_n(4);
+    let json = network.snapshot().to_json();
+    let loaded = Network::from_snapshot(NetworkSnapshot::from_json(&json).unwrap()).unwrap();
+    assert_eq!(loaded.snapshot().to_json(), json);
+    assert_eq!(
+        (loaded.config.subcommittee_hash)(1),
+        crate::bank::splitmix64(1)
+    );
+    //xorshift has a short cycle at the default seed 0 but still builds,
+    //its snapshots can't be loaded
+    let mut network = Network::with_config(config(xorshift));
+    network.step_n(4);
+    let json = network.snapshot().to_json();
+    assert!(json.contains("\"subcommittee_hash\":\"custom\""));
+    assert!(NetworkSnapshot::from_json(&json).is_err());
+}
+
+#[test]
+fn test_from_stake_csv() {
+    let path = std::env::temp_dir().join(format!("tower_sim_stake_{}.csv", std::process::id()));
+    let path = path.to_str().unwrap();
+    let csv = |validators: usize, stake: &dyn Fn(ID) -> i64| {
+        let rows: Vec<_> = (0..validators)
+            .map(|id| format!("{},{}", id, stake(id)))
+            .collect();
+        fs::write(path, format!("validator_id,stake\n{}\n", rows.join("\n"))).unwrap();
+        Network::from_stake_csv(path)
+    };
+    let network = csv(NUM_NODES, &|id| 100 + id as i64).unwrap();
+    assert_eq!(network.config.stakes[7], 107);
+    assert_eq!(network.banks.stake(7), 107);
+    //validators past NUM_NODES share a node
+    let network = csv(2 * NUM_NODES + 1, &|id| id as i64).unwrap();
+    assert_eq!(network.banks.stake(0), NUM_NODES + 2 * NUM_NODES);
+    assert_eq!(network.banks.stake(1), 1 + NUM_NODES + 1);
+    //and nodes past the validators have none
+    let network = csv(3, &|_| 5).unwrap();
+    assert_eq!(network.banks.total_stake(), 15);
+    assert_eq!(network.banks.stake(3), 0);
+    assert_eq!(
+        csv(NUM_NODES, &|id| if id == 7 { -1 } else { 100 })
+            .err()
+            .unwrap()
+            .line,
+        9
+    );
+    assert_eq!(csv(NUM_NODES, &|_| 0).err().unwrap().line, 0);
+    assert_eq!(csv(3, &|_| i64::MAX).err().unwrap().line, 0);
+    fs::remove_file(path).unwrap();
+    assert_eq!(Network::from_stake_csv(path).err().unwrap().line, 0);
+}
+
+#[test]
+fn test_weighted_stake() {
+    //node 0 has 3/4 of the stake
+    let mut stakes = vec![1; NUM_NODES];
+    stakes[0] = 3 * NUM_NODES as u64;
+    let config = Config {
+        stakes,
+        ..Config::default()
+    };
+    let network = Network::with_config(config.clone());
+    let total = 4 * NUM_NODES - 1;
+    assert_eq!(network.banks.total_stake(), total);
+    assert_eq!(
+        network.fault_tolerance(),
+        total - supermajority_stake(total, crate::bank::SUPERMAJORITY) - 1
+    );
+    //it roots on its own, as the only leader
+    let mut network = Network::with_config(Config {
+        leader_schedule: LeaderSchedule::Fixed(vec![0]),
+        ..config.clone()
+    });
+    for id in 1..NUM_NODES {
+        network.halt_node(id);
     }
+    network.step_n(64);
+    assert!(network.node_root(0).slot > 0);
+    assert!(network.super_root() > 0);
+    network.check_invariants().unwrap();
+    let loaded =
+        Network::from_snapshot(NetworkSnapshot::from_json(&network.snapshot().to_json()).unwrap())
+            .unwrap();
+    assert_eq!(loaded.banks.stake(0), 3 * NUM_NODES);
+    assert_eq!(
+        loaded.banks.rebuilt_fork_weights(),
+        network.banks.fork_weights
+    );
+    //and the other nodes can't without it
+    let mut network = Network::with_config(config);
+    network.halt_node(0);
+    assert_eq!(network.fault_tolerance(), 0);
+    network.step_n(64);
+    assert_eq!(network.super_root(), 0);
+    assert_eq!(network.node_root(1).slot, 0);
 }
@@ -1,34 +1,265 @@
 use crate::bank::Banks;
-use crate::bank::{Bank, Block, ID, NUM_NODES};
-use crate::tower::{Slot, Tower, Vote};
+use crate::bank::{minority_stake, Block, ID, SUPERMAJORITY};
+use crate::fork_choice::{self, ForkChoice, HeaviestFork};
+use crate::inclusion_policy::InclusionPolicy;
+use crate::snapshot::{NodeSnapshot, SnapshotError};
+use crate::tower::{Slot, Tower, TowerError, Vote, LOCKOUT_BASE};
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::rc::Rc;
 
 pub const THRESHOLD: usize = 6;
+pub const BLOCK_GC_THRESHOLD: usize = 1024;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RejectReason {
+    //a vote still live in the tower is not in the heaviest fork
+    Lockout(Vote),
+    //this increased lockout doesn't have 2/3+ of the nodes in the bank
+    Threshold(Vote),
+    //switching forks without 1/3+ of the nodes on other forks
+    OptimisticConfirmation,
+    //the heaviest slot is below the tower's latest vote or root
+    Tower(TowerError),
+    //force_vote: the node is offline
+    Halted,
+    //force_vote: the slot has no bank
+    MissingBank,
+    //the fork choice rule picked a slot that has no bank or doesn't
+    //descend from lowest_root
+    ForkChoice,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CatchUpError {
+    //a vote that is not on the root's fork and is still locked out
+    //at the root's slot
+    LockedOut(Vote),
+    //the node hasn't seen a block on lowest_root's fork
+    RootNotVisible(Vote),
+}
+
+//a vote a node wanted to make but didn't
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoteRejection {
+    pub id: ID,
+    pub slot: Slot,
+    pub reason: RejectReason,
+}
+
+/// What `Node::vote` would do, see `Node::simulate_vote`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VoteOutcome {
+    //offline nodes don't vote
+    Halted,
+    //the tower already has a vote on the heaviest slot
+    AlreadyVoted(Slot),
+    //the vote on the heaviest slot fails a check
+    Rejected(VoteRejection),
+    //the vote on slot passes, leaving the node with tower
+    Vote {
+        slot: Slot,
+        tower: Box<Tower>,
+        //the last tower vote is not in the new fork
+        switching: bool,
+    },
+}
+
+impl VoteOutcome {
+    //the heaviest slot the node chose, if it isn't halted
+    pub fn slot(&self) -> Option<Slot> {
+        match self {
+            VoteOutcome::Halted => None,
+            VoteOutcome::AlreadyVoted(slot) | VoteOutcome::Vote { slot, .. } => Some(*slot),
+            VoteOutcome::Rejected(rejection) => Some(rejection.slot),
+        }
+    }
+}
+
+/// How often votes needed a switching proof, see `optimistic_conf_check`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SwitchStats {
+    //votes whose last tower vote is not in the new fork
+    pub required: usize,
+    //switches with 1/3+ of the nodes on other forks
+    pub passed: usize,
+    //switches rejected with RejectReason::OptimisticConfirmation
+    pub blocked: usize,
+}
+
+impl std::ops::AddAssign for SwitchStats {
+    fn add_assign(&mut self, other: Self) {
+        self.required += other.required;
+        self.passed += other.passed;
+        self.blocked += other.blocked;
+    }
+}
+
+#[derive(Clone)]
 pub struct Node {
     pub id: ID,
+    //byzantine nodes also vote on the heaviest competing fork
+    pub equivocate: bool,
+    //offline nodes don't vote, their last votes still count
+    pub halted: bool,
+    //votes but is skipped by the leader schedule
+    pub non_leader: bool,
+    //slots the node's clock is ahead of the network, negative when it
+    //lags. blocks after the node's slot are not considered for voting
+    pub slot_offset: i64,
+    //lockouts of base^threshold and above are threshold checked, the
+    //bank still confirms them with the network's threshold
+    pub threshold: usize,
+    //fraction of the stake that is a supermajority, switching forks
+    //needs the rest of the stake on other forks
+    pub supermajority: (u64, u64),
+    //votes go this many blocks behind the heaviest slot, see
+    //Config::vote_lag
+    pub vote_lag: usize,
+    //blocks below the tower root are dropped once the local view has
+    //more than this many
+    pub block_gc_threshold: usize,
     //local view of the bank forks
     blocks: HashSet<Slot>,
     tower: Tower,
     pub heaviest_fork: Vec<Slot>,
+    //shared by the clones in Network's undo history
+    pub fork_choice: Rc<dyn ForkChoice>,
+    switch_stats: SwitchStats,
+    //rejected votes, each a slot the tower didn't grow on
+    wasted_vote_slots: usize,
+    //whether the last vote that reached the threshold check passed it
+    threshold_passed: Option<bool>,
+    //times that result flipped between vote attempts
+    threshold_oscillations: usize,
 }
 
 impl Node {
     pub fn zero(id: ID) -> Self {
+        Self::with_lockout_base(id, LOCKOUT_BASE)
+    }
+    pub fn with_lockout_base(id: ID, base: u64) -> Self {
         let mut blocks = HashSet::new();
         blocks.insert(0);
         Node {
             id,
+            equivocate: false,
+            halted: false,
+            non_leader: false,
+            slot_offset: 0,
+            threshold: THRESHOLD,
+            supermajority: SUPERMAJORITY,
+            vote_lag: 0,
+            block_gc_threshold: BLOCK_GC_THRESHOLD,
             blocks,
-            tower: Tower::default(),
+            tower: Tower::with_base(base),
             heaviest_fork: vec![0],
+            fork_choice: Rc::new(HeaviestFork),
+            switch_stats: SwitchStats::default(),
+            wasted_vote_slots: 0,
+            threshold_passed: None,
+            threshold_oscillations: 0,
+        }
+    }
+
+    pub fn snapshot(&self) -> NodeSnapshot {
+        let mut blocks: Vec<_> = self.blocks.iter().copied().collect();
+        blocks.sort_unstable();
+        NodeSnapshot {
+            id: self.id,
+            equivocate: self.equivocate,
+            halted: self.halted,
+            non_leader: self.non_leader,
+            slot_offset: self.slot_offset,
+            threshold: self.threshold,
+            block_gc_threshold: self.block_gc_threshold,
+            blocks,
+            tower: self.tower.clone(),
+            heaviest_fork: self.heaviest_fork.clone(),
+            fork_choice: self.fork_choice.name().to_string(),
+            switch_stats: self.switch_stats,
+            wasted_vote_slots: self.wasted_vote_slots,
+            threshold_passed: self.threshold_passed,
+            threshold_oscillations: self.threshold_oscillations,
         }
     }
 
+    pub fn from_snapshot(snap: NodeSnapshot) -> Result<Self, SnapshotError> {
+        let fork_choice =
+            fork_choice::from_name(&snap.fork_choice).ok_or_else(|| SnapshotError {
+                message: format!(
+                    "can't restore fork_choice {:?} of node {}",
+                    snap.fork_choice, snap.id
+                ),
+            })?;
+        Ok(Node {
+            id: snap.id,
+            equivocate: snap.equivocate,
+            halted: snap.halted,
+            non_leader: snap.non_leader,
+            slot_offset: snap.slot_offset,
+            threshold: snap.threshold,
+            supermajority: SUPERMAJORITY,
+            vote_lag: 0,
+            block_gc_threshold: snap.block_gc_threshold,
+            blocks: snap.blocks.into_iter().collect(),
+            tower: snap.tower,
+            heaviest_fork: snap.heaviest_fork,
+            fork_choice: fork_choice.into(),
+            switch_stats: snap.switch_stats,
+            wasted_vote_slots: snap.wasted_vote_slots,
+            threshold_passed: snap.threshold_passed,
+            threshold_oscillations: snap.threshold_oscillations,
+        })
+    }
+
+    //adopts lowest_root instead of voting up to it, for a node whose
+    //tower fell behind the banks, e.g. one that restarted without it.
+    //the node's heaviest visible fork has to descend from lowest_root.
+    //a halted node's own root in the banks holds lowest_root back, so
+    //this doesn't move it past where it stopped. votes at or below the
+    //new root are dropped, as are votes on other forks that expired
+    //before it
+    pub fn catch_up(&mut self, banks: &Banks) -> Result<(), CatchUpError> {
+        let root = banks.lowest_root;
+        if root.slot <= self.tower.root.slot {
+            return Ok(());
+        }
+        let weights = self.visible_weights(banks, Slot::MAX);
+        let heaviest = self.fork_choice.choose(&weights, banks);
+        if !weights.contains_key(&heaviest) || !banks.compute_fork(heaviest).contains(&root.slot) {
+            return Err(CatchUpError::RootNotVisible(root));
+        }
+        let root_fork = banks.compute_fork(root.slot);
+        let on_fork = |v: &Vote| {
+            if v.slot <= root.slot {
+                root_fork.contains(&v.slot)
+            } else {
+                banks.compute_fork(v.slot).contains(&root.slot)
+            }
+        };
+        if let Some(v) = self
+            .tower
+            .votes
+            .iter()
+            .find(|v| !on_fork(v) && v.expiry() >= root.slot)
+        {
+            return Err(CatchUpError::LockedOut(*v));
+        }
+        let mut tower = Tower::with_base(self.tower.base);
+        tower.root = root;
+        for v in self.tower.votes.iter().rev() {
+            if v.slot > root.slot && on_fork(v) {
+                tower.votes.push_front(*v);
+            }
+        }
+        self.tower = tower;
+        self.blocks.insert(root.slot);
+        Ok(())
+    }
     pub fn set_active_block(&mut self, slot: Slot) {
         self.blocks.insert(slot);
-        if self.blocks.len() > 1024 {
+        if self.blocks.len() > self.block_gc_threshold {
             self.gc();
         }
     }
@@ -37,24 +268,20 @@ impl Node {
         self.blocks.retain(|x| *x >= self.tower.root.slot);
     }
 
-    fn threshold_check(&self, tower: &Tower, banks: &HashMap<Slot, Bank>) -> bool {
+    //returns the first increased lockout that fails the threshold
+    fn threshold_check(&self, tower: &Tower, banks: &Banks) -> Option<Vote> {
         let vote = tower.votes.front().unwrap();
-        let bank = banks.get(&vote.slot).unwrap();
+        let bank = banks.fork_map.get(&vote.slot).unwrap();
         //check if the bank lockouts are increased
-        let proposed_lockouts = bank.nodes[self.id].get_incrased_lockouts(1 << THRESHOLD, tower);
-        if proposed_lockouts.is_empty() {
-            return true;
-        }
+        let proposed_lockouts =
+            bank.nodes[self.id].get_incrased_lockouts(tower.lockout(self.threshold), tower);
         for (slot, lockout) in proposed_lockouts {
             let v = Vote { slot, lockout };
-            if !bank.threshold_slot(&v) {
-                if self.id < 4 {
-                    println!("{} {} threshold check failed {:?}", self.id, bank.slot, v);
-                }
-                return false;
+            if !bank.threshold_slot(banks.threshold, &v) {
+                return Some(v);
             }
         }
-        true
+        None
     }
 
     fn optimistic_conf_check(
@@ -73,11 +300,11 @@ impl Node {
         if new_fork.iter().find(|x| **x == last_vote.slot).is_some() {
             return true;
         }
-        //all the recent forks but those decending from the last vote must have > 1/3 votes
+        //all the recent forks but those decending from the last vote must have > 1/3 of the stake
         let mut total = 0;
         let last_vote_fork = banks.compute_fork(last_vote.slot);
         for (slot, stake) in fork_weights {
-            if self.blocks.get(slot).is_none() {
+            if !self.blocks.contains(slot) {
                 continue;
             }
             if *slot <= last_vote.slot {
@@ -94,104 +321,213 @@ impl Node {
                 total += stake;
             }
         }
-        total > NUM_NODES / 3
+        total > minority_stake(banks.total_stake(), self.supermajority)
+    }
+    pub fn switch_stats(&self) -> SwitchStats {
+        self.switch_stats
+    }
+    pub fn wasted_vote_slots(&self) -> usize {
+        self.wasted_vote_slots
+    }
+    pub fn threshold_oscillations(&self) -> usize {
+        self.threshold_oscillations
+    }
+    pub fn tower(&self) -> &Tower {
+        &self.tower
+    }
+    pub fn set_tower(&mut self, tower: Tower) {
+        self.tower = tower;
     }
     pub fn votes(&self) -> Vec<Vote> {
         let mut votes = self.tower.votes();
         for v in &mut votes {
-            v.lockout = 2;
+            v.lockout = self.tower.base;
         }
         votes
     }
+    //the conflicting vote set of an equivocating node: its tower
+    //history that is in the heaviest competing fork, plus a vote
+    //on the tip of that fork
+    pub fn equivocation_votes(&self, banks: &Banks) -> Option<Vec<Vote>> {
+        if !self.equivocate {
+            return None;
+        }
+        let slot = banks
+            .fork_weights
+            .iter()
+            .filter(|(x, _)| self.blocks.contains(x) && !self.heaviest_fork.contains(x))
+            .map(|(x, y)| (y, x))
+            .max()
+            .map(|(_, x)| *x)?;
+        let fork = banks.compute_fork(slot);
+        let mut votes: Vec<_> = self
+            .votes()
+            .into_iter()
+            .filter(|v| v.slot < slot && fork.contains(&v.slot))
+            .collect();
+        votes.push(self.tower.vote(slot));
+        Some(votes)
+    }
     pub fn make_block(&self, slot: Slot, votes: Vec<(ID, Vec<Vote>)>) -> Block {
+        //every vote has to be in the fork, not just the latest, since an
+        //injected tower can have older votes on another fork
+        let fork: HashSet<_> = self.heaviest_fork.iter().copied().collect();
+        let in_fork = |votes: &[Vote]| votes.iter().all(|v| fork.contains(&v.slot));
+        //a tower off the fork from a node that also has one on it is
+        //kept as evidence of the equivocation
+        let voters: HashSet<ID> = votes
+            .iter()
+            .filter(|(_, votes)| !votes.is_empty() && in_fork(votes))
+            .map(|(id, _)| *id)
+            .collect();
         let votes: Vec<_> = votes
             .into_iter()
-            .filter(|(_, votes)| {
-                votes.last().is_some()
-                    && self
-                        .heaviest_fork
-                        .iter()
-                        .find(|x| **x == votes.last().unwrap().slot)
-                        .is_some()
-            })
+            .filter(|(id, votes)| !votes.is_empty() && voters.contains(id))
             .collect();
         Block {
             slot,
-            parent: *self.heaviest_fork.get(0).unwrap_or(&0),
+            parent: *self.heaviest_fork.first().unwrap_or(&0),
             votes,
         }
     }
 
+    //make_block, then the policy picks which of the votes go in. banks
+    //has the parent's subcommittee
+    pub fn make_block_with_policy(
+        &self,
+        slot: Slot,
+        votes: Vec<(ID, Vec<Vote>)>,
+        policy: &InclusionPolicy,
+        banks: &Banks,
+    ) -> Block {
+        let mut block = self.make_block(slot, votes);
+        let subcommittee = banks
+            .fork_map
+            .get(&block.parent)
+            .map(|b| b.subcom.primary.clone())
+            .unwrap_or_default();
+        block.votes = policy.select(slot, block.votes, &subcommittee);
+        block
+    }
+
     //the latest vote in tower is from the heaviest fork
     //the second to last vote that is still live in tower
     //must be in the heaviest fork, which is the same fork
     //that generated the vote
-    pub fn lockout_check(&self, tower: &Tower) -> bool {
-        if tower.votes.len() > 0 {
+    pub fn lockout_check(&self, heaviest_fork: &[Slot], tower: &Tower) -> bool {
+        if !tower.votes.is_empty() {
             for e in &tower.votes {
-                if self.heaviest_fork.iter().find(|x| **x == e.slot).is_none() {
+                if heaviest_fork.iter().find(|x| **x == e.slot).is_none() {
                     return false;
                 }
             }
             true
         } else {
-            let rv = self
-                .heaviest_fork
+            let rv = heaviest_fork
                 .iter()
                 .find(|x| **x == tower.root.slot)
                 .is_some();
             assert!(
                 rv,
                 "heaviest fork doesn't contain root {} {:?}",
-                tower.root.slot, self.heaviest_fork
+                tower.root.slot, heaviest_fork
             );
             rv
         }
     }
 
-    pub fn vote(&mut self, banks: &Banks) {
-        //filter out for blocks visibile to this nodes partition
-        let weights: HashMap<Slot, usize> = banks
+    fn rejection(&self, slot: Slot, reason: RejectReason) -> VoteOutcome {
+        VoteOutcome::Rejected(VoteRejection {
+            id: self.id,
+            slot,
+            reason,
+        })
+    }
+
+    //the network slot as seen by this node's clock, never negative
+    pub fn local_slot(&self, slot: Slot) -> Slot {
+        (slot as i64 + self.slot_offset).max(0) as Slot
+    }
+
+    //what vote would do on the heaviest fork at the network slot,
+    //without changing the tower or the heaviest fork
+    pub fn simulate_vote(&self, banks: &Banks, slot: Slot) -> VoteOutcome {
+        if self.halted {
+            return VoteOutcome::Halted;
+        }
+        let (weights, heaviest_slot) = self.heaviest_slot(banks, slot);
+        self.check_vote(banks, &weights, self.lagged_slot(banks, heaviest_slot))
+    }
+
+    //the visible fork weights and the heaviest slot at the network slot
+    fn heaviest_slot(&self, banks: &Banks, slot: Slot) -> (HashMap<Slot, usize>, Slot) {
+        //a lagging node still sees the root
+        let now = self.local_slot(slot).max(banks.lowest_root.slot);
+        let weights = self.visible_weights(banks, now);
+        //compute the heaviest slot
+        let heaviest_slot = self.fork_choice.choose(&weights, banks);
+        (weights, heaviest_slot)
+    }
+
+    //the ancestor vote_lag blocks behind slot, never below the root
+    fn lagged_slot(&self, banks: &Banks, slot: Slot) -> Slot {
+        if self.vote_lag == 0 {
+            return slot;
+        }
+        let fork = banks.compute_fork(slot);
+        let root = fork
+            .iter()
+            .position(|s| *s == banks.lowest_root.slot)
+            .unwrap_or(fork.len() - 1);
+        fork[self.vote_lag.min(root)]
+    }
+
+    //filter out for blocks visibile to this nodes partition
+    fn visible_weights(&self, banks: &Banks, now: Slot) -> HashMap<Slot, usize> {
+        banks
             .fork_weights
             .iter()
-            .filter(|(x, _)| self.blocks.contains(x))
+            .filter(|(x, _)| self.blocks.contains(x) && **x <= now)
             .map(|(x, y)| (*x, *y))
-            .collect();
-        //compute the heaviest slot
-        let heaviest_slot = weights
-            .iter()
-            .map(|(x, y)| (y, x))
-            .max()
-            .map(|(_, y)| *y)
-            .unwrap_or(0);
-        //recursively find the fork for the heaviest slot
+            .collect()
+    }
+
+    //runs the tower, lockout, threshold and optimistic confirmation
+    //checks for a vote on heaviest_slot
+    fn check_vote(
+        &self,
+        banks: &Banks,
+        weights: &HashMap<Slot, usize>,
+        heaviest_slot: Slot,
+    ) -> VoteOutcome {
+        //recursively find the fork for the heaviest slot. this holds in a
+        //split that never heals too, lowest_root stops at or below the
+        //last common ancestor so every side's fork descends from it
         let heaviest_fork = banks.compute_fork(heaviest_slot);
-        assert!(heaviest_fork
-            .iter()
-            .find(|x| **x == banks.lowest_root.slot)
-            .is_some());
-        self.heaviest_fork = heaviest_fork;
+        if !banks.fork_map.contains_key(&heaviest_slot)
+            || !heaviest_fork.contains(&banks.lowest_root.slot)
+        {
+            return self.rejection(heaviest_slot, RejectReason::ForkChoice);
+        }
         //simulate the vote
         let mut tower = self.tower.clone();
-        let vote = Vote {
-            slot: heaviest_slot,
-            lockout: 2,
-        };
+        let vote = self.tower.vote(heaviest_slot);
         //apply this vote and expire all the old votes
-        if tower.apply(&vote).is_err() {
-            //already voted
-            return;
+        match tower.apply(&vote) {
+            Ok(()) => (),
+            Err(TowerError::AlreadyVoted) => return VoteOutcome::AlreadyVoted(heaviest_slot),
+            Err(e) => return self.rejection(heaviest_slot, RejectReason::Tower(e)),
         }
         //check if the lockouts aren't violated
         //remaining votes in tower should be in the heaviest fork
-        if !self.lockout_check(&tower) {
-            if self.id < 4 {
-                println!(
-                    "{} recent vote is locked out from the heaviest fork {:?}",
-                    self.id, tower.votes[1]
-                );
-            }
-            return;
+        if !self.lockout_check(&heaviest_fork, &tower) {
+            let locked = tower
+                .votes
+                .iter()
+                .find(|v| !heaviest_fork.contains(&v.slot))
+                .copied()
+                .unwrap_or(tower.root);
+            return self.rejection(heaviest_slot, RejectReason::Lockout(locked));
         }
         //grab the bank that this is voting on, and simulate the
         //votes applying to the banks tower state
@@ -201,39 +537,25 @@ impl Node {
         let proposed = tower.votes();
         assert!(proposed[0].slot <= proposed.last().unwrap().slot);
         for mut v in proposed {
-            v.lockout = 2;
+            v.lockout = tower.base;
             let _ = result.apply(&v);
         }
         //check if the simulated result exceeds the thershold check
         //if the simulation increases the lockout, the bank should have
         //2/3+ nodes voting on the locked out slot
-        if !self.threshold_check(&result, &banks.fork_map) {
-            if self.id < 4 {
-                println!("{} THRESHOLD CHECK FAILED", self.id);
-                for (v, t) in self.tower.votes.iter().zip(result.votes.iter()) {
-                    println!(
-                        "{} LOCKOUT {:?} {} {:?} {}",
-                        self.id,
-                        v,
-                        bank.calc_threshold_slot(1, v),
-                        t,
-                        bank.calc_threshold_slot(2, t)
-                    );
-                }
-            }
-            return;
+        if let Some(failed) = self.threshold_check(&result, banks) {
+            return self.rejection(heaviest_slot, RejectReason::Threshold(failed));
         }
         //check if this node is switching forks. if its switching forks then
         //at least 1/3 of the nodes must be voting on forks that are not the last
         //vote's fork
-        if !self.optimistic_conf_check(&self.heaviest_fork, &weights, banks) {
-            if self.id < 4 {
-                println!("{} OC CHECK FAILED", self.id);
-            }
-            return;
-        }
-        if self.id < 4 {
-            println!("{} voting {:?} root: {:?}", self.id, vote, self.tower.root);
+        let switching = self
+            .tower
+            .votes
+            .front()
+            .is_some_and(|v| !heaviest_fork.contains(&v.slot));
+        if !self.optimistic_conf_check(&heaviest_fork, weights, banks) {
+            return self.rejection(heaviest_slot, RejectReason::OptimisticConfirmation);
         }
         for v in 1..tower.votes.len() {
             let v = &tower.votes[v];
@@ -244,14 +566,118 @@ impl Node {
                 self.tower
             );
         }
-        if self.tower.root != tower.root {
-            if self.id < 4 {
-                println!(
-                    "{} updated root {:?} old root: {:?}",
-                    self.id, tower.root, self.tower.root
+        VoteOutcome::Vote {
+            slot: heaviest_slot,
+            tower: Box::new(tower),
+            switching,
+        }
+    }
+
+    //returns why the vote on the heaviest fork was rejected, if it was
+    pub fn vote(&mut self, banks: &Banks, slot: Slot) -> Option<VoteRejection> {
+        let outcome = self.simulate_vote(banks, slot);
+        let rejection = self.cast(banks, outcome);
+        //the vote lags behind, but blocks still build on the tip
+        if self.vote_lag > 0 && !self.halted {
+            let (_, heaviest_slot) = self.heaviest_slot(banks, slot);
+            self.heaviest_fork = banks.compute_fork(heaviest_slot);
+        }
+        rejection
+    }
+
+    //votes on slot instead of the heaviest slot, still running every
+    //check, so tests can build adversarial voting patterns. Ok only if
+    //the vote was cast
+    pub fn force_vote(&mut self, slot: Slot, banks: &Banks) -> Result<(), VoteRejection> {
+        if self.halted {
+            return Err(self.rejected(slot, RejectReason::Halted));
+        }
+        if !banks.fork_map.contains_key(&slot) {
+            return Err(self.rejected(slot, RejectReason::MissingBank));
+        }
+        let weights = self.visible_weights(banks, slot.max(banks.lowest_root.slot));
+        let outcome = self.check_vote(banks, &weights, slot);
+        let already_voted = matches!(outcome, VoteOutcome::AlreadyVoted(_));
+        match self.cast(banks, outcome) {
+            Some(rejection) => Err(rejection),
+            None if already_voted => {
+                Err(self.rejected(slot, RejectReason::Tower(TowerError::AlreadyVoted)))
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn rejected(&self, slot: Slot, reason: RejectReason) -> VoteRejection {
+        VoteRejection {
+            id: self.id,
+            slot,
+            reason,
+        }
+    }
+
+    fn cast(&mut self, banks: &Banks, outcome: VoteOutcome) -> Option<VoteRejection> {
+        let slot = outcome.slot()?;
+        //a slot the fork choice rule made up has no fork to follow
+        let made_up =
+            matches!(&outcome, VoteOutcome::Rejected(r) if r.reason == RejectReason::ForkChoice);
+        if !made_up {
+            self.heaviest_fork = banks.compute_fork(slot);
+        }
+        //the optimistic confirmation check only runs after the threshold
+        //check passed, tower and lockout rejections never reach it
+        let passed = match &outcome {
+            VoteOutcome::Vote { .. } => Some(true),
+            VoteOutcome::Rejected(rejection) => match rejection.reason {
+                RejectReason::Threshold(_) => Some(false),
+                RejectReason::OptimisticConfirmation => Some(true),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(passed) = passed {
+            if self.threshold_passed.is_some_and(|p| p != passed) {
+                self.threshold_oscillations += 1;
+            }
+            self.threshold_passed = Some(passed);
+        }
+        match outcome {
+            VoteOutcome::Halted | VoteOutcome::AlreadyVoted(_) => None,
+            VoteOutcome::Rejected(rejection) => {
+                self.wasted_vote_slots += 1;
+                //only a switch can fail the optimistic confirmation check
+                if rejection.reason == RejectReason::OptimisticConfirmation {
+                    self.switch_stats.required += 1;
+                    self.switch_stats.blocked += 1;
+                }
+                Some(rejection)
+            }
+            VoteOutcome::Vote {
+                slot,
+                tower,
+                switching,
+            } => {
+                if switching {
+                    self.switch_stats.required += 1;
+                    self.switch_stats.passed += 1;
+                }
+                log::trace!(
+                    "id={} slot={} voting root={:?}",
+                    self.id,
+                    slot,
+                    self.tower.root
                 );
+                if self.tower.root != tower.root {
+                    log::trace!(
+                        "id={} slot={} updated root={:?} old={:?}",
+                        self.id,
+                        slot,
+                        tower.root,
+                        self.tower.root
+                    );
+                }
+                self.tower = *tower;
+                None
             }
         }
-        self.tower = tower;
     }
 }
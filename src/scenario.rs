@@ -0,0 +1,106 @@
+use crate::bank::ID;
+use std::fs;
+
+/// One line of a scenario script.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    //step N
+    Step(usize),
+    //partition N
+    Partition(usize),
+    //partition_weighted 0.4 0.35 0.25
+    PartitionWeighted(Vec<f64>),
+    //repair [N], defaults to healing all partitions
+    Repair(usize),
+    //byzantine ID...
+    Byzantine(Vec<ID>),
+    //delay N
+    Delay(usize),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ScenarioError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// A list of commands parsed from a text script, one command per line.
+/// Blank lines and lines starting with `#` are ignored.
+///
+/// ```text
+/// step 256
+/// partition 2
+/// step 32
+/// repair
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Scenario {
+    //(line number, command)
+    pub commands: Vec<(usize, Command)>,
+}
+
+fn arg<T: std::str::FromStr>(line: usize, word: &str) -> Result<T, ScenarioError> {
+    word.parse().map_err(|_| ScenarioError {
+        line,
+        message: format!("invalid argument {:?}", word),
+    })
+}
+
+fn args<T: std::str::FromStr>(line: usize, words: &[&str]) -> Result<Vec<T>, ScenarioError> {
+    words.iter().map(|w| arg(line, w)).collect()
+}
+
+impl Scenario {
+    pub fn parse(text: &str) -> Result<Self, ScenarioError> {
+        let mut commands = vec![];
+        for (i, text) in text.lines().enumerate() {
+            let line = i + 1;
+            let words: Vec<_> = text.split_whitespace().collect();
+            if words.is_empty() || words[0].starts_with('#') {
+                continue;
+            }
+            let command = match (words[0], &words[1..]) {
+                ("step", [n]) => Command::Step(arg(line, n)?),
+                ("partition", [n]) => Command::Partition(arg(line, n)?),
+                ("partition_weighted", fractions) if !fractions.is_empty() => {
+                    Command::PartitionWeighted(args(line, fractions)?)
+                }
+                ("repair", []) => Command::Repair(0),
+                ("repair", [n]) => Command::Repair(arg(line, n)?),
+                ("byzantine", ids) => Command::Byzantine(args(line, ids)?),
+                ("delay", [n]) => Command::Delay(arg(line, n)?),
+                _ => {
+                    return Err(ScenarioError {
+                        line,
+                        message: format!("unknown command {:?}", text.trim()),
+                    })
+                }
+            };
+            commands.push((line, command));
+        }
+        Ok(Scenario { commands })
+    }
+
+    pub fn load(path: &str) -> Result<Self, ScenarioError> {
+        let text = fs::read_to_string(path).map_err(|e| ScenarioError {
+            line: 0,
+            message: format!("{}: {}", path, e),
+        })?;
+        Self::parse(&text)
+    }
+}
+
+#[test]
+fn test_parse() {
+    let scenario = Scenario::parse("step 10\n\n# heal\nrepair\nbyzantine 1 2").unwrap();
+    assert_eq!(
+        scenario.commands,
+        vec![
+            (1, Command::Step(10)),
+            (4, Command::Repair(0)),
+            (5, Command::Byzantine(vec![1, 2])),
+        ]
+    );
+    let err = Scenario::parse("step 1\nsplit 2").unwrap_err();
+    assert_eq!(err.line, 2);
+}
@@ -0,0 +1,83 @@
+use crate::bank::{Banks, ID};
+use crate::tower::{Slot, Vote};
+use std::collections::BTreeMap;
+
+/// Two votes from the same node that no honest node could have made:
+/// `b` is on a fork that doesn't descend from `a`, inside `a`'s lockout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlashEvidence {
+    pub id: ID,
+    pub a: Vote,
+    pub b: Vote,
+}
+
+impl Banks {
+    //scans every node's votes across all the banks, ordered by node id
+    //then by the slots of the two votes. votes on gc'd slots are skipped
+    //since their ancestry is unknown
+    pub fn detect_slashable(&self) -> Vec<SlashEvidence> {
+        //each node's votes keyed by slot, with the smallest lockout seen.
+        //that's the lockout the vote started with, an honest node is
+        //locked out for at least that long
+        let mut votes: Vec<BTreeMap<Slot, u64>> = vec![];
+        for bank in self.fork_map.values() {
+            votes.resize(bank.nodes.len(), BTreeMap::new());
+            for (id, tower) in bank.nodes.iter().enumerate() {
+                for v in &tower.votes {
+                    if !self.fork_map.contains_key(&v.slot) {
+                        continue;
+                    }
+                    let e = votes[id].entry(v.slot).or_insert(v.lockout);
+                    *e = (*e).min(v.lockout);
+                }
+            }
+        }
+        let mut evidence = vec![];
+        for (id, node_votes) in votes.iter().enumerate() {
+            let node_votes: Vec<Vote> = node_votes
+                .iter()
+                .map(|(slot, lockout)| Vote {
+                    slot: *slot,
+                    lockout: *lockout,
+                })
+                .collect();
+            for (i, a) in node_votes.iter().enumerate() {
+                for b in &node_votes[i + 1..] {
+                    if b.slot > a.expiry() {
+                        break;
+                    }
+                    if !self.compute_fork(b.slot).contains(&a.slot) {
+                        evidence.push(SlashEvidence { id, a: *a, b: *b });
+                    }
+                }
+            }
+        }
+        evidence
+    }
+}
+
+#[test]
+fn test_detect_slashable() {
+    use crate::bank::BlockBuilder;
+    let mut banks = Banks::default();
+    let block = |slot, parent| BlockBuilder::default().slot(slot).parent(parent);
+    banks.apply(&block(1, 0).build()).unwrap();
+    banks.apply(&block(2, 0).build()).unwrap();
+    //node 0 votes on 1, then on 2 inside 1's lockout on the other fork
+    banks
+        .apply(&block(3, 1).vote(0, 1).vote(1, 1).build())
+        .unwrap();
+    assert_eq!(banks.detect_slashable(), vec![]);
+    banks.apply(&block(4, 2).vote(0, 2).build()).unwrap();
+    assert_eq!(
+        banks.detect_slashable(),
+        vec![SlashEvidence {
+            id: 0,
+            a: Vote::new(1),
+            b: Vote::new(2),
+        }]
+    );
+    //after 1's lockout expires node 1 can switch
+    banks.apply(&block(5, 4).vote(1, 4).build()).unwrap();
+    assert_eq!(banks.detect_slashable().len(), 1);
+}
@@ -0,0 +1,283 @@
+use crate::bank::{default_hash, splitmix64, Block, HashFn, Subcommittee, ID, NUM_NODES};
+use crate::config::Config;
+use crate::fork_choice;
+use crate::node::{RejectReason, SwitchStats, VoteRejection};
+use crate::tower::{Slot, Tower, Vote, DEPTH};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashSet;
+
+#[derive(Serialize, Deserialize)]
+pub struct BankSnapshot {
+    pub slot: Slot,
+    pub parent: Slot,
+    pub children: Vec<Slot>,
+    pub frozen: bool,
+    pub nodes: Vec<Tower>,
+    pub subcom: Subcommittee,
+    pub equivocations: Vec<ID>,
+}
+
+/// The fork tree and the metrics tracked alongside it.
+/// fork_weights are only informational, they are rebuilt from the
+/// banks when the snapshot is loaded.
+#[derive(Serialize, Deserialize)]
+pub struct BanksSnapshot {
+    //sorted by slot
+    pub fork_map: Vec<BankSnapshot>,
+    //pruned banks kept for Banks::historical_bank, sorted by slot
+    pub history: Vec<BankSnapshot>,
+    pub fork_weights: Vec<(Slot, usize)>,
+    pub lowest_root: Vote,
+    pub equivocations: Vec<(Slot, ID)>,
+    pub root_latencies: Vec<(Slot, usize)>,
+    pub max_children_seen: usize,
+    pub max_orphan_depth: usize,
+    pub rooted_chain_length: usize,
+    pub confirmed: Vec<Slot>,
+}
+
+//the fork choice rule is saved by name, see fork_choice::from_name.
+//the subcommittee hash is saved by name in the config, see HASHES
+#[derive(Serialize, Deserialize)]
+pub struct NodeSnapshot {
+    pub id: ID,
+    pub equivocate: bool,
+    pub halted: bool,
+    pub non_leader: bool,
+    pub slot_offset: i64,
+    pub threshold: usize,
+    pub block_gc_threshold: usize,
+    pub blocks: Vec<Slot>,
+    pub tower: Tower,
+    pub heaviest_fork: Vec<Slot>,
+    pub fork_choice: String,
+    pub switch_stats: SwitchStats,
+    pub wasted_vote_slots: usize,
+    pub threshold_passed: Option<bool>,
+    pub threshold_oscillations: usize,
+}
+
+/// The full state of a network between two steps, saved as json by
+/// `Network::save_checkpoint`.
+#[derive(Serialize, Deserialize)]
+pub struct NetworkSnapshot {
+    pub config: Config,
+    pub slot: Slot,
+    pub num_partitions: usize,
+    pub partition_map: Vec<usize>,
+    pub reachability: Vec<Vec<bool>>,
+    pub partitioned_blocks: Vec<(ID, Slot)>,
+    pub delay_slots: usize,
+    //(delivery slot, producer, block)
+    pub in_flight: Vec<(Slot, ID, Slot)>,
+    pub banks: BanksSnapshot,
+    pub healed: Vec<ID>,
+    pub heal_rate: f64,
+    pub heal_progress: f64,
+    pub nodes: Vec<NodeSnapshot>,
+}
+
+/// A vote rejection in a `StepEvent`, the reason is the `RejectReason`
+/// variant in snake case.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct RejectionEvent {
+    pub id: ID,
+    pub slot: Slot,
+    pub reason: String,
+}
+
+impl From<&VoteRejection> for RejectionEvent {
+    fn from(r: &VoteRejection) -> Self {
+        let reason = match r.reason {
+            RejectReason::Lockout(_) => "lockout",
+            RejectReason::Threshold(_) => "threshold",
+            RejectReason::OptimisticConfirmation => "optimistic_confirmation",
+            RejectReason::Tower(_) => "tower",
+            RejectReason::Halted => "halted",
+            RejectReason::MissingBank => "missing_bank",
+            RejectReason::ForkChoice => "fork_choice",
+        };
+        RejectionEvent {
+            id: r.id,
+            slot: r.slot,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+/// One line of `Network::enable_event_stream`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct StepEvent {
+    pub slot: Slot,
+    //None when no block was applied
+    pub leader: Option<ID>,
+    pub root: Vote,
+    pub fork_count: usize,
+    pub rejections: Vec<RejectionEvent>,
+}
+
+//the subcommittee hashes a snapshot can name, a config with any other
+//hash can be saved but not loaded
+const HASHES: [(&str, HashFn); 2] = [("default_hash", default_hash), ("splitmix64", splitmix64)];
+
+//function pointers don't compare reliably, so a hash is named by its output
+pub(crate) fn hash_name(hash: HashFn) -> &'static str {
+    HASHES
+        .iter()
+        .find(|(_, h)| (0..8).all(|x| h(x) == hash(x)))
+        .map(|(name, _)| *name)
+        .unwrap_or("custom")
+}
+
+//saves a hash by name, see HASHES
+pub(crate) fn serialize_hash<S: Serializer>(hash: &HashFn, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(hash_name(*hash))
+}
+
+pub(crate) fn deserialize_hash<'de, D: Deserializer<'de>>(d: D) -> Result<HashFn, D::Error> {
+    let name = String::deserialize(d)?;
+    match HASHES.iter().find(|(n, _)| *n == name) {
+        Some((_, hash)) => Ok(*hash),
+        None => Err(serde::de::Error::custom(format!(
+            "can't restore subcommittee_hash {:?}",
+            name
+        ))),
+    }
+}
+
+//hash sets are saved sorted so equal states give equal json
+pub(crate) fn serialize_sorted<S: Serializer>(ids: &HashSet<ID>, s: S) -> Result<S::Ok, S::Error> {
+    let mut ids: Vec<_> = ids.iter().copied().collect();
+    ids.sort_unstable();
+    ids.serialize(s)
+}
+
+/// A recorded block stream, see `Network::start_recording`.
+pub fn blocks_to_json(blocks: &[Block]) -> String {
+    serde_json::to_string(blocks).unwrap()
+}
+
+pub fn blocks_from_json(text: &str) -> Result<Vec<Block>, SnapshotError> {
+    serde_json::from_str(text).or_else(|e| error(e.to_string()))
+}
+
+fn check_tower(tower: &Tower) -> Result<(), SnapshotError> {
+    match Tower::valid_base(tower.base) {
+        true => Ok(()),
+        false => error(format!("invalid lockout base {}", tower.base)),
+    }
+}
+
+impl BanksSnapshot {
+    //every bank has a tower per node, lowest_root is in the fork_map
+    //and the parents and children of the banks above it are too
+    pub fn check(&self) -> Result<(), SnapshotError> {
+        let slots: HashSet<Slot> = self.fork_map.iter().map(|b| b.slot).collect();
+        if !slots.contains(&self.lowest_root.slot) {
+            return error(format!(
+                "lowest_root {} isn't in the fork_map",
+                self.lowest_root.slot
+            ));
+        }
+        for b in self.fork_map.iter().chain(&self.history) {
+            if b.nodes.len() != NUM_NODES {
+                return error(format!("bank {} has {} towers", b.slot, b.nodes.len()));
+            }
+            for t in &b.nodes {
+                check_tower(t)?;
+            }
+        }
+        for b in &self.fork_map {
+            if let Some(child) = b.children.iter().find(|c| !slots.contains(c)) {
+                return error(format!("child {} of {} is missing", child, b.slot));
+            }
+            if b.slot != self.lowest_root.slot && !slots.contains(&b.parent) {
+                return error(format!("parent {} of {} is missing", b.parent, b.slot));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SnapshotError {
+    pub message: String,
+}
+
+fn error<T>(message: String) -> Result<T, SnapshotError> {
+    Err(SnapshotError { message })
+}
+
+impl NetworkSnapshot {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, SnapshotError> {
+        let snap: NetworkSnapshot = serde_json::from_str(text).or_else(|e| error(e.to_string()))?;
+        snap.check()?;
+        Ok(snap)
+    }
+
+    //what Network::from_snapshot rejects, the file parsed but its
+    //state is inconsistent
+    pub fn check(&self) -> Result<(), SnapshotError> {
+        self.config
+            .check()
+            .or_else(|e| error(format!("config: {}", e)))?;
+        if self.nodes.len() != NUM_NODES {
+            return error(format!(
+                "{} nodes, expected {}",
+                self.nodes.len(),
+                NUM_NODES
+            ));
+        }
+        for (id, n) in self.nodes.iter().enumerate() {
+            if n.id != id {
+                return error(format!("node {} has id {}", id, n.id));
+            }
+            if n.threshold == 0 || n.threshold > DEPTH {
+                return error(format!("node {} has threshold {}", id, n.threshold));
+            }
+            check_tower(&n.tower)?;
+            if fork_choice::from_name(&n.fork_choice).is_none() {
+                return error(format!(
+                    "can't restore fork_choice {:?} of node {}",
+                    n.fork_choice, id
+                ));
+            }
+        }
+        let unknown = |what: &str, id: ID| match id < NUM_NODES {
+            true => Ok(()),
+            false => error(format!("{} has unknown node {}", what, id)),
+        };
+        for id in &self.healed {
+            unknown("healed", *id)?;
+        }
+        for (id, _) in &self.partitioned_blocks {
+            unknown("partitioned_blocks", *id)?;
+        }
+        for (_, id, _) in &self.in_flight {
+            unknown("in_flight", *id)?;
+        }
+        if !self.partition_map.is_empty()
+            && (self.partition_map.len() != NUM_NODES
+                || self.partition_map.iter().any(|p| *p >= self.num_partitions))
+        {
+            return error(format!(
+                "partition_map doesn't map {} nodes to {} partitions",
+                NUM_NODES, self.num_partitions
+            ));
+        }
+        if !self.reachability.is_empty()
+            && (self.reachability.len() != self.num_partitions
+                || self
+                    .reachability
+                    .iter()
+                    .any(|r| r.len() != self.num_partitions))
+        {
+            return error(format!("reachability isn't {0}x{0}", self.num_partitions));
+        }
+        self.banks.check()
+    }
+}
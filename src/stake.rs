@@ -0,0 +1,97 @@
+use crate::bank::ID;
+use std::fs;
+
+#[derive(Debug, PartialEq)]
+pub struct StakeError {
+    //0 for errors about the whole file
+    pub line: usize,
+    pub message: String,
+}
+
+fn error<T>(line: usize, message: String) -> Result<T, StakeError> {
+    Err(StakeError { line, message })
+}
+
+/// A stake distribution read from `validator_id,stake` rows, indexed by id.
+/// An optional `validator_id,stake` header and blank lines are skipped.
+///
+/// ```text
+/// validator_id,stake
+/// 0,5000
+/// 1,1200
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StakeDistribution {
+    pub stakes: Vec<u64>,
+}
+
+impl StakeDistribution {
+    //ids have to be contiguous from 0, in order
+    pub fn parse(text: &str) -> Result<Self, StakeError> {
+        let mut stakes = vec![];
+        for (i, text) in text.lines().enumerate() {
+            let line = i + 1;
+            let text = text.trim();
+            if text.is_empty() || (stakes.is_empty() && text == "validator_id,stake") {
+                continue;
+            }
+            let (id, stake) = match text.split(',').map(str::trim).collect::<Vec<_>>()[..] {
+                [id, stake] => (id, stake),
+                _ => return error(line, format!("expected validator_id,stake: {:?}", text)),
+            };
+            let id: ID = match id.parse() {
+                Ok(id) => id,
+                Err(_) => return error(line, format!("invalid validator_id {:?}", id)),
+            };
+            if id != stakes.len() {
+                return error(
+                    line,
+                    format!(
+                        "validator_id {} should be {}, ids must be contiguous from 0",
+                        id,
+                        stakes.len()
+                    ),
+                );
+            }
+            let stake: i128 = match stake.parse() {
+                Ok(stake) => stake,
+                Err(_) => return error(line, format!("invalid stake {:?}", stake)),
+            };
+            if stake < 0 {
+                return error(line, format!("negative stake {}", stake));
+            }
+            match u64::try_from(stake) {
+                Ok(stake) => stakes.push(stake),
+                Err(_) => return error(line, format!("stake {} doesn't fit in a u64", stake)),
+            }
+        }
+        Ok(StakeDistribution { stakes })
+    }
+
+    pub fn load(path: &str) -> Result<Self, StakeError> {
+        let text = fs::read_to_string(path).map_err(|e| StakeError {
+            line: 0,
+            message: format!("{}: {}", path, e),
+        })?;
+        Self::parse(&text)
+    }
+}
+
+#[test]
+fn test_parse() {
+    let stakes = StakeDistribution::parse("validator_id,stake\n0,5000\n\n1, 1200\n").unwrap();
+    assert_eq!(stakes.stakes, vec![5000, 1200]);
+    let line = |text: &str| StakeDistribution::parse(text).unwrap_err().line;
+    //gaps, duplicates and ids out of order
+    assert_eq!(line("0,1\n2,1"), 2);
+    assert_eq!(line("0,1\n0,1"), 2);
+    assert_eq!(line("1,1"), 1);
+    assert_eq!(line("0,1\n1,-5"), 2);
+    assert_eq!(line("0,1\n1,1.5"), 2);
+    assert_eq!(line("0,1,2"), 1);
+    assert_eq!(line("0,1\nvalidator_id,stake"), 2);
+    assert_eq!(
+        StakeDistribution::parse("0,-1").unwrap_err().message,
+        "negative stake -1"
+    );
+}
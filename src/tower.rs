@@ -1,19 +1,31 @@
+use crate::bank::Banks;
 use std::collections::HashMap;
 use std::collections::VecDeque;
+use std::fmt;
+use std::ops::{Index, IndexMut};
 
 pub const DEPTH: usize = 16;
+//lockouts grow by this factor unless a tower is built with_base
+pub const LOCKOUT_BASE: u64 = 2;
 
 pub type Slot = u64;
 
-#[derive(Clone, Copy, PartialOrd, PartialEq, Eq, Ord, Debug)]
+//ordered by slot, then lockout
+#[derive(
+    Clone, Copy, PartialOrd, PartialEq, Eq, Ord, Debug, serde::Serialize, serde::Deserialize,
+)]
 pub struct Vote {
     pub slot: Slot,
     pub lockout: u64,
 }
 
 impl Vote {
+    //a new vote for a tower with the default LOCKOUT_BASE
     pub fn new(slot: Slot) -> Self {
-        Vote { slot, lockout: 2 }
+        Vote {
+            slot,
+            lockout: LOCKOUT_BASE,
+        }
     }
     pub fn zero() -> Self {
         Vote {
@@ -21,74 +33,451 @@ impl Vote {
             lockout: 1 << DEPTH,
         }
     }
+    //last slot the vote is locked out for
+    pub fn expiry(&self) -> Slot {
+        self.slot + self.lockout
+    }
+}
+
+//slot:lockout
+impl fmt::Display for Vote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.slot, self.lockout)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TowerError {
+    //the tower already has a vote on this slot
+    AlreadyVoted,
+    //the tower has a vote on a higher slot
+    NonMonotonic,
+    //the slot is at or below the tower's root
+    RootRegression,
+    //the towers being merged have votes the other is missing
+    //inside their lockouts
+    ConflictingFork,
+    //the towers being merged have different lockout bases, (self's,
+    //the other's)
+    BaseMismatch(u64, u64),
+    //verify_tower: the slot isn't in the banks
+    UnknownSlot(Slot),
+    //verify_tower: the vote isn't an ancestor of the latest vote
+    OffFork(Slot),
+    //verify_tower: the vote's lockout is smaller than the one above it
+    LockoutOrder(Slot),
+    //verify_tower: the root isn't an ancestor of the vote
+    RootNotAncestor(Slot),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    //the first byte is not ENCODING_VERSION
+    Version(u8),
+    //the input ends in the middle of a value
+    Truncated,
+    //a varint doesn't fit in a u64
+    Overflow,
+    //a lockout base below 2, or so large base^DEPTH overflows
+    Base(u64),
+    //more votes than a tower can hold
+    TooManyVotes(u64),
+    //bytes left over after the last vote
+    TrailingBytes(usize),
 }
 
-#[derive(Clone, Debug, PartialEq)]
+//first byte of Tower::encode
+pub const ENCODING_VERSION: u8 = 1;
+
+/// Fixed capacity stack of tower votes, the front is the latest vote.
+/// A tower never holds more than DEPTH votes, so unlike a VecDeque
+/// cloning it doesn't allocate.
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(into = "Vec<Vote>", try_from = "Vec<Vote>")]
+pub struct VoteStack {
+    votes: [Vote; DEPTH],
+    len: usize,
+}
+
+impl Default for VoteStack {
+    fn default() -> Self {
+        VoteStack {
+            votes: [Vote {
+                slot: 0,
+                lockout: 0,
+            }; DEPTH],
+            len: 0,
+        }
+    }
+}
+
+impl VoteStack {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn front(&self) -> Option<&Vote> {
+        self.iter().next()
+    }
+    pub fn back(&self) -> Option<&Vote> {
+        self.iter().last()
+    }
+    pub fn iter(&self) -> std::slice::Iter<'_, Vote> {
+        self.votes[..self.len].iter()
+    }
+    pub fn push_front(&mut self, vote: Vote) {
+        assert!(self.len < DEPTH, "vote stack overflow");
+        self.votes.copy_within(0..self.len, 1);
+        self.votes[0] = vote;
+        self.len += 1;
+    }
+    pub fn pop_front(&mut self) -> Option<Vote> {
+        let front = self.front().copied()?;
+        self.votes.copy_within(1..self.len, 0);
+        self.len -= 1;
+        Some(front)
+    }
+    pub fn pop_back(&mut self) -> Option<Vote> {
+        let back = self.back().copied()?;
+        self.len -= 1;
+        Some(back)
+    }
+}
+
+impl Index<usize> for VoteStack {
+    type Output = Vote;
+    fn index(&self, i: usize) -> &Vote {
+        &self.votes[..self.len][i]
+    }
+}
+
+impl IndexMut<usize> for VoteStack {
+    fn index_mut(&mut self, i: usize) -> &mut Vote {
+        &mut self.votes[..self.len][i]
+    }
+}
+
+//latest vote first
+impl From<VoteStack> for Vec<Vote> {
+    fn from(stack: VoteStack) -> Self {
+        stack.iter().copied().collect()
+    }
+}
+
+impl TryFrom<Vec<Vote>> for VoteStack {
+    type Error = String;
+    fn try_from(votes: Vec<Vote>) -> Result<Self, String> {
+        if votes.len() > DEPTH {
+            return Err(format!("{} votes, a tower holds {}", votes.len(), DEPTH));
+        }
+        let mut stack = VoteStack::default();
+        for v in votes.into_iter().rev() {
+            stack.push_front(v);
+        }
+        Ok(stack)
+    }
+}
+
+impl<'a> IntoIterator for &'a VoteStack {
+    type Item = &'a Vote;
+    type IntoIter = std::slice::Iter<'a, Vote>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl PartialEq for VoteStack {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl PartialEq<VecDeque<Vote>> for VoteStack {
+    fn eq(&self, other: &VecDeque<Vote>) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl fmt::Debug for VoteStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Tower {
-    pub votes: VecDeque<Vote>,
+    pub votes: VoteStack,
     pub root: Vote,
+    //new votes are locked out for base slots, and a lockout is
+    //multiplied by base each time the vote above it catches up
+    pub base: u64,
 }
 
 impl Default for Tower {
     fn default() -> Self {
-        Tower {
-            votes: VecDeque::with_capacity(DEPTH),
-            root: Vote {
-                slot: 0,
-                lockout: 1 << DEPTH,
-            },
+        Self::with_base(LOCKOUT_BASE)
+    }
+}
+
+//root=12 votes=[14:2,16:4], votes oldest first
+impl fmt::Display for Tower {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "root={} votes=[", self.root.slot)?;
+        for (i, v) in self.votes.iter().rev().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", v)?;
         }
+        write!(f, "]")
     }
 }
 
 impl Tower {
-    pub fn apply(&mut self, vote: &Vote) -> Result<(), ()> {
-        assert_eq!(vote.lockout, 2);
-        //pop all the expired votes
-        let mut expired = None;
-        if self.root.slot >= vote.slot {
-            return Err(());
+    //the compact form from Display, for debug output
+    pub fn display(&self) -> String {
+        self.to_string()
+    }
+    //true if towers can be built with base, see with_base
+    pub fn valid_base(base: u64) -> bool {
+        base > 1 && base.checked_pow(DEPTH as u32).is_some()
+    }
+    pub fn with_base(base: u64) -> Self {
+        assert!(base > 1, "lockout base {} must be at least 2", base);
+        let lockout = base
+            .checked_pow(DEPTH as u32)
+            .unwrap_or_else(|| panic!("lockout base {} overflows base^{}", base, DEPTH));
+        Tower {
+            votes: VoteStack::default(),
+            root: Vote { slot: 0, lockout },
+            base,
         }
-        for (i, v) in self.votes.iter().enumerate() {
-            //apply only new votes
-            if v.slot >= vote.slot {
-                return Err(());
+    }
+    //lockout of a vote with depth - 1 votes stacked on it, base^depth
+    pub fn lockout(&self, depth: usize) -> u64 {
+        self.base.pow(depth as u32)
+    }
+    //a new vote on slot for this tower
+    pub fn vote(&self, slot: Slot) -> Vote {
+        Vote {
+            slot,
+            lockout: self.base,
+        }
+    }
+    //version byte, then LEB128 varints: base, root slot and lockout, the
+    //number of votes, and each vote's slot and lockout, oldest first
+    pub fn encode(&self) -> Vec<u8> {
+        fn varint(out: &mut Vec<u8>, mut val: u64) {
+            while val >= 0x80 {
+                out.push(val as u8 | 0x80);
+                val >>= 7;
             }
-            if v.slot + v.lockout >= vote.slot {
-                break;
+            out.push(val as u8);
+        }
+        let mut out = vec![ENCODING_VERSION];
+        varint(&mut out, self.base);
+        varint(&mut out, self.root.slot);
+        varint(&mut out, self.root.lockout);
+        varint(&mut out, self.votes.len() as u64);
+        for v in self.votes.iter().rev() {
+            varint(&mut out, v.slot);
+            varint(&mut out, v.lockout);
+        }
+        out
+    }
+    //the inverse of encode, the votes are not validated
+    pub fn decode(bytes: &[u8]) -> Result<Tower, DecodeError> {
+        let version = *bytes.first().ok_or(DecodeError::Truncated)?;
+        if version != ENCODING_VERSION {
+            return Err(DecodeError::Version(version));
+        }
+        let mut rest = &bytes[1..];
+        let mut varint = || {
+            let mut val: u64 = 0;
+            for shift in (0..64).step_by(7) {
+                let (byte, tail) = rest.split_first().ok_or(DecodeError::Truncated)?;
+                rest = tail;
+                let bits = (*byte & 0x7f) as u64;
+                if bits << shift >> shift != bits {
+                    return Err(DecodeError::Overflow);
+                }
+                val |= bits << shift;
+                if byte & 0x80 == 0 {
+                    return Ok(val);
+                }
+            }
+            Err(DecodeError::Overflow)
+        };
+        let base = varint()?;
+        if !Tower::valid_base(base) {
+            return Err(DecodeError::Base(base));
+        }
+        let mut tower = Tower::with_base(base);
+        tower.root = Vote {
+            slot: varint()?,
+            lockout: varint()?,
+        };
+        let len = varint()?;
+        if len > DEPTH as u64 {
+            return Err(DecodeError::TooManyVotes(len));
+        }
+        for _ in 0..len {
+            tower.votes.push_front(Vote {
+                slot: varint()?,
+                lockout: varint()?,
+            });
+        }
+        if !rest.is_empty() {
+            return Err(DecodeError::TrailingBytes(rest.len()));
+        }
+        Ok(tower)
+    }
+    //votes a vote on at_slot would pop, latest first. a vote expires
+    //once at_slot is past its expiry, and only the votes above the
+    //latest unexpired one pop
+    pub fn expired_votes(&self, at_slot: Slot) -> Vec<Vote> {
+        self.votes
+            .iter()
+            .take_while(|v| v.expiry() < at_slot)
+            .copied()
+            .collect()
+    }
+    //checks the votes are above the root, with slots increasing and
+    //lockouts decreasing from the oldest vote to the latest
+    pub fn validate(&self) -> Result<(), TowerError> {
+        let mut prev = self.root;
+        for v in self.votes.iter().rev() {
+            if v.slot <= self.root.slot {
+                return Err(TowerError::RootRegression);
             }
-            if v.slot + v.lockout < vote.slot {
-                expired = Some(i);
+            if v.slot <= prev.slot || v.lockout >= prev.lockout || v.lockout < self.base {
+                return Err(TowerError::NonMonotonic);
             }
+            prev = *v;
         }
-        if let Some(i) = expired {
-            for _ in 0..i {
-                self.votes.pop_front();
+        Ok(())
+    }
+    pub fn apply(&mut self, vote: &Vote) -> Result<(), TowerError> {
+        assert_eq!(vote.lockout, self.base);
+        if self.root.slot >= vote.slot {
+            return Err(TowerError::RootRegression);
+        }
+        //apply only new votes
+        if let Some(latest) = self.votes.front() {
+            if latest.slot == vote.slot {
+                return Err(TowerError::AlreadyVoted);
             }
+            if latest.slot > vote.slot {
+                return Err(TowerError::NonMonotonic);
+            }
+        }
+        //pop all the expired votes
+        for _ in 0..self.expired_votes(vote.slot).len() {
+            self.votes.pop_front();
         }
-        self.votes.push_front(vote.clone());
+        self.votes.push_front(*vote);
         for i in 1..DEPTH {
             if i >= self.votes.len() {
                 break;
             }
-            //double this lockout if the previous one is equal to this one
+            //grow this lockout if the previous one is equal to this one
             if self.votes[i].lockout == self.votes[i - 1].lockout {
-                self.votes[i].lockout = self.votes[i].lockout * 2;
+                self.votes[i].lockout *= self.base;
             }
         }
-        let mut root = false;
-        if let Some(oldest) = self.votes.back() {
-            if oldest.lockout == 1 << DEPTH {
-                self.root = *oldest;
-                root = true;
+        //the oldest vote becomes the root once its lockout reaches
+        //base^DEPTH, which takes DEPTH votes stacked on it
+        if self
+            .votes
+            .back()
+            .is_some_and(|v| v.lockout == self.lockout(DEPTH))
+        {
+            self.root = self.votes.pop_back().unwrap();
+        }
+        //anything at DEPTH votes deep is locked out for base^DEPTH
+        //and must have been popped as the root
+        assert!(self.votes.len() < DEPTH, "tower overflow {:?}", self.votes);
+        Ok(())
+    }
+    //adopt the higher root and the live votes of both towers, keeping
+    //the larger lockout of a shared vote. a tower doesn't know the
+    //ancestry of its slots, so a vote only one tower has that falls
+    //inside the lockout of a vote only the other has is treated as
+    //a vote on a conflicting fork
+    pub fn merge(&mut self, other: &Tower) -> Result<(), TowerError> {
+        if self.base != other.base {
+            return Err(TowerError::BaseMismatch(self.base, other.base));
+        }
+        let root = self.root.max(other.root);
+        let live = |t: &Tower| -> HashMap<Slot, u64> {
+            t.votes
+                .iter()
+                .filter(|v| v.slot > root.slot)
+                .map(|v| (v.slot, v.lockout))
+                .collect()
+        };
+        let (ours, theirs) = (live(self), live(other));
+        let only = |a: &HashMap<Slot, u64>, b: &HashMap<Slot, u64>| -> Vec<Vote> {
+            a.iter()
+                .filter(|(slot, _)| !b.contains_key(slot))
+                .map(|(slot, lockout)| Vote {
+                    slot: *slot,
+                    lockout: *lockout,
+                })
+                .collect()
+        };
+        for v in only(&ours, &theirs) {
+            for w in only(&theirs, &ours) {
+                if (v.slot < w.slot && w.slot <= v.expiry())
+                    || (w.slot < v.slot && v.slot <= w.expiry())
+                {
+                    return Err(TowerError::ConflictingFork);
+                }
             }
         }
-        if root {
-            self.votes.pop_back();
+        let mut union = ours;
+        for (slot, lockout) in theirs {
+            let e = union.entry(slot).or_insert(lockout);
+            *e = (*e).max(lockout);
+        }
+        let mut union: Vec<_> = union
+            .into_iter()
+            .map(|(slot, lockout)| Vote { slot, lockout })
+            .collect();
+        union.sort();
+        //replay the votes oldest first, expiring them like apply does
+        let mut votes = VoteStack::default();
+        for v in union {
+            while votes.front().is_some_and(|f| f.expiry() < v.slot) {
+                votes.pop_front();
+            }
+            if votes.front().is_some_and(|f| f.lockout <= v.lockout) {
+                return Err(TowerError::NonMonotonic);
+            }
+            votes.push_front(v);
+        }
+        self.root = root;
+        self.votes = votes;
+        while self
+            .votes
+            .back()
+            .is_some_and(|b| b.lockout >= self.lockout(DEPTH))
+        {
+            self.root = self.votes.pop_back().unwrap();
         }
         Ok(())
     }
-    //check if tower has more lockouts on a slot then in self
+    //self is the tower a bank has stored for a node, tower is the one the
+    //node proposes after voting. returns the slots whose lockout grew from
+    //self to tower, mapped to the grown lockout in tower:
+    // - the root of tower, if self's lockout on that slot is lower
+    // - every vote in tower with a lockout of at least skip_lockout and a
+    //   lower lockout on the same slot in self
+    //slots self has no vote on are new rather than increased, so they are
+    //left out, like votes tower expired and unchanged lockouts. a lockout
+    //can grow by several factors of base when the bank missed votes
     pub fn get_incrased_lockouts(&self, skip_lockout: u64, tower: &Tower) -> HashMap<Slot, u64> {
         let mut rv = HashMap::new();
         let mut set = HashMap::new();
@@ -96,7 +485,10 @@ impl Tower {
         for e in &self.votes {
             set.insert(e.slot, e.lockout);
         }
-        if *set.get(&tower.root.slot).unwrap() < tower.root.lockout {
+        if set
+            .get(&tower.root.slot)
+            .is_some_and(|l| *l < tower.root.lockout)
+        {
             rv.insert(tower.root.slot, tower.root.lockout);
         }
         for e in &tower.votes {
@@ -105,7 +497,6 @@ impl Tower {
             }
             let lockout = *set.get(&e.slot).unwrap_or(&u64::MAX);
             if lockout < e.lockout {
-                assert_eq!(lockout * 2, e.lockout);
                 rv.insert(e.slot, e.lockout);
             }
         }
@@ -118,49 +509,168 @@ impl Tower {
         votes
     }
 
+    //lockout on a slot this tower has voted on, including the root
+    pub fn lockout_at(&self, slot: Slot) -> Option<u64> {
+        if slot == self.root.slot {
+            return Some(self.root.lockout);
+        }
+        self.votes
+            .iter()
+            .find(|v| v.slot == slot)
+            .map(|v| v.lockout)
+    }
+
     pub fn latest_vote(&self) -> Option<&Vote> {
         self.votes.front()
     }
+
+    //the slot apply(vote) would make the root, None if it doesn't root
+    //a slot or the vote would be rejected
+    pub fn would_root(&self, vote: &Vote) -> Option<Slot> {
+        if vote.lockout != self.base {
+            return None;
+        }
+        let mut tower = self.clone();
+        tower.apply(vote).ok()?;
+        (tower.root != self.root).then_some(tower.root.slot)
+    }
+}
+
+//checks an untrusted tower against banks: every vote is on the fork of
+//the latest vote, lockouts don't shrink with depth, and the root is an
+//ancestor of every vote. a root below the banks' lowest root has been
+//gc'd, so it's accepted if the votes descend from the lowest root
+pub fn verify_tower(tower: &Tower, banks: &Banks) -> Result<(), Vec<TowerError>> {
+    let mut errors = vec![];
+    let root = tower.root.slot;
+    let gcd_root = root < banks.lowest_root.slot;
+    if !gcd_root && !banks.fork_map.contains_key(&root) {
+        errors.push(TowerError::UnknownSlot(root));
+    }
+    let fork = match tower.latest_vote() {
+        Some(latest) => banks.compute_fork(latest.slot),
+        None => vec![],
+    };
+    //votes from the latest to the oldest
+    let mut above: Option<&Vote> = None;
+    for v in tower.votes.iter() {
+        if v.slot <= root {
+            errors.push(TowerError::RootRegression);
+        }
+        if let Some(above) = above {
+            if v.lockout < above.lockout {
+                errors.push(TowerError::LockoutOrder(v.slot));
+            }
+        }
+        above = Some(v);
+        if !banks.fork_map.contains_key(&v.slot) {
+            errors.push(TowerError::UnknownSlot(v.slot));
+            continue;
+        }
+        if !fork.contains(&v.slot) {
+            errors.push(TowerError::OffFork(v.slot));
+            continue;
+        }
+        let ancestor = if gcd_root {
+            banks.lowest_root.slot
+        } else {
+            root
+        };
+        if !banks.compute_fork(v.slot).contains(&ancestor) {
+            errors.push(TowerError::RootNotAncestor(v.slot));
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+//new votes on slots below 2^32, what Tower::apply takes from a default
+//tower. stacked lockouts only come from applying votes, see Tower
+#[cfg(feature = "proptest")]
+impl proptest::arbitrary::Arbitrary for Vote {
+    type Parameters = ();
+    type Strategy = proptest::strategy::BoxedStrategy<Vote>;
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        use proptest::strategy::Strategy;
+        (0..1u64 << 32).prop_map(Vote::new).boxed()
+    }
+}
+
+//a default tower with a vote_sequence applied to it
+#[cfg(feature = "proptest")]
+impl proptest::arbitrary::Arbitrary for Tower {
+    type Parameters = ();
+    type Strategy = proptest::strategy::BoxedStrategy<Tower>;
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        use proptest::strategy::Strategy;
+        vote_sequence(4 * DEPTH)
+            .prop_map(|votes| {
+                let mut tower = Tower::default();
+                for v in &votes {
+                    tower.apply(v).unwrap();
+                }
+                tower
+            })
+            .boxed()
+    }
+}
+
+//up to max_len new votes on strictly increasing slots above 0, the
+//gaps between them are small enough that lockouts stack up
+#[cfg(feature = "proptest")]
+pub fn vote_sequence(max_len: usize) -> impl proptest::strategy::Strategy<Value = Vec<Vote>> {
+    use proptest::strategy::Strategy;
+    proptest::collection::vec(1..2 * DEPTH as Slot, 0..=max_len).prop_map(|gaps| {
+        gaps.into_iter()
+            .scan(0, |slot, gap| {
+                *slot += gap;
+                Some(Vote::new(*slot))
+            })
+            .collect()
+    })
 }
 
 #[test]
-fn test_compare_lockouts_1() {
+fn test_incrased_lockouts_1() {
     let mut t1 = Tower::default();
     let mut t2 = Tower::default();
     let v = Vote {
         slot: 1,
         lockout: 2,
     };
-    assert!(!t1.compare_lockouts(0, &t2));
-    t1.apply(&v);
-    t2.apply(&v);
-    assert!(!t1.compare_lockouts(0, &t2));
+    assert!(t1.get_incrased_lockouts(0, &t2).is_empty());
+    t1.apply(&v).unwrap();
+    t2.apply(&v).unwrap();
+    assert!(t1.get_incrased_lockouts(0, &t2).is_empty());
 }
 
 #[test]
-fn test_compare_lockouts_2() {
+fn test_incrased_lockouts_2() {
     let mut t1 = Tower::default();
     let mut t2 = Tower::default();
-    assert!(!t1.compare_lockouts(0, &t2));
+    assert!(t1.get_incrased_lockouts(0, &t2).is_empty());
     let v1 = Vote {
         slot: 1,
         lockout: 2,
     };
-    t1.apply(&v1);
+    t1.apply(&v1).unwrap();
     let v2 = Vote {
         slot: 2,
         lockout: 2,
     };
-    t2.apply(&v1);
-    t2.apply(&v2);
-    assert!(t1.compare_lockouts(0, &t2));
+    t2.apply(&v1).unwrap();
+    t2.apply(&v2).unwrap();
+    assert!(!t1.get_incrased_lockouts(0, &t2).is_empty());
 }
 
 #[test]
-fn test_compare_lockouts_3() {
+fn test_incrased_lockouts_3() {
     let mut t1 = Tower::default();
     let mut t2 = Tower::default();
-    assert!(!t1.compare_lockouts(0, &t2));
+    assert!(t1.get_incrased_lockouts(0, &t2).is_empty());
     let v1 = Vote {
         slot: 1,
         lockout: 2,
@@ -174,14 +684,12 @@ fn test_compare_lockouts_3() {
         lockout: 2,
     };
 
-    t1.apply(&v1);
-    t1.apply(&v2);
-    t2.apply(&v1);
-    t2.apply(&v2);
-    t2.apply(&v3);
-    println!("votes {:?}", t2.votes);
-    println!("votes {:?}", t1.votes);
-    assert!(!t1.compare_lockouts(0, &t2));
+    t1.apply(&v1).unwrap();
+    t1.apply(&v2).unwrap();
+    t2.apply(&v1).unwrap();
+    t2.apply(&v2).unwrap();
+    t2.apply(&v3).unwrap();
+    assert!(t1.get_incrased_lockouts(0, &t2).is_empty());
 }
 
 #[test]
@@ -191,8 +699,31 @@ fn test_apply() {
         slot: 1,
         lockout: 2,
     };
-    t.apply(&v);
-    assert_eq!(t.latest_vote(), v);
+    t.apply(&v).unwrap();
+    assert_eq!(t.latest_vote(), Some(&v));
+}
+
+#[test]
+fn test_lockout_at() {
+    let mut t = Tower::default();
+    for i in 1..4 {
+        let _ = t.apply(&Vote::new(i));
+    }
+    assert_eq!(t.lockout_at(0), Some(1 << DEPTH));
+    assert_eq!(t.lockout_at(1), Some(8));
+    assert_eq!(t.lockout_at(2), Some(4));
+    assert_eq!(t.lockout_at(3), Some(2));
+    assert_eq!(t.lockout_at(4), None);
+}
+
+#[test]
+fn test_apply_errors() {
+    let mut t = Tower::default();
+    assert_eq!(t.apply(&Vote::new(0)), Err(TowerError::RootRegression));
+    assert_eq!(t.apply(&Vote::new(3)), Ok(()));
+    assert_eq!(t.apply(&Vote::new(3)), Err(TowerError::AlreadyVoted));
+    assert_eq!(t.apply(&Vote::new(2)), Err(TowerError::NonMonotonic));
+    assert_eq!(t.votes.len(), 1);
 }
 
 #[test]
@@ -203,7 +734,7 @@ fn test_root() {
             slot: i as u64,
             lockout: 2,
         };
-        t.apply(&v);
+        t.apply(&v).unwrap();
     }
     let root = Vote {
         slot: 1,
@@ -220,7 +751,7 @@ fn test_pop_votes() {
             slot: i as u64,
             lockout: 2,
         };
-        t.apply(&v);
+        t.apply(&v).unwrap();
     }
     let root = Vote {
         slot: 0,
@@ -228,7 +759,6 @@ fn test_pop_votes() {
     };
     assert_eq!(t.root, root);
     let mut test_votes: VecDeque<_> = (1..DEPTH)
-        .into_iter()
         .map(|x| Vote {
             slot: DEPTH as u64 - x as u64,
             lockout: 1 << x,
@@ -240,7 +770,7 @@ fn test_pop_votes() {
         slot: DEPTH as u64 + 8,
         lockout: 2,
     };
-    t.apply(&vote);
+    t.apply(&vote).unwrap();
     assert_eq!(t.root, root);
     let _ = test_votes.pop_front();
     let _ = test_votes.pop_front();
@@ -252,29 +782,343 @@ fn test_pop_votes() {
         slot: DEPTH as u64 + 9,
         lockout: 2,
     };
-    t.apply(&vote);
+    t.apply(&vote).unwrap();
     test_votes.push_front(vote);
-    test_votes[1].lockout = 2 * test_votes[1].lockout;
+    test_votes[1].lockout *= 2;
     assert_eq!(t.votes, test_votes);
 
     let vote = Vote {
         slot: DEPTH as u64 + 10,
         lockout: 2,
     };
-    t.apply(&vote);
+    t.apply(&vote).unwrap();
     test_votes.push_front(vote);
-    test_votes[1].lockout = 2 * test_votes[1].lockout;
-    test_votes[2].lockout = 2 * test_votes[2].lockout;
+    test_votes[1].lockout *= 2;
+    test_votes[2].lockout *= 2;
     assert_eq!(t.votes, test_votes);
 
     let vote = Vote {
         slot: DEPTH as u64 + 11,
         lockout: 2,
     };
-    t.apply(&vote);
+    t.apply(&vote).unwrap();
     let root = Vote {
         slot: 1,
         lockout: 1 << DEPTH,
     };
     assert_eq!(t.root, root);
 }
+
+#[test]
+fn test_lockout_base() {
+    let mut t = Tower::with_base(3);
+    assert_eq!(t.lockout_at(0), Some(3u64.pow(DEPTH as u32)));
+    for i in 1..4 {
+        let _ = t.apply(&t.vote(i));
+    }
+    assert_eq!(t.lockout_at(1), Some(27));
+    assert_eq!(t.lockout_at(2), Some(9));
+    assert_eq!(t.lockout_at(3), Some(3));
+}
+
+#[test]
+fn test_vote_order() {
+    let mut votes = vec![Vote::new(3), Vote::zero(), Vote::new(1)];
+    votes.sort();
+    assert_eq!(votes, vec![Vote::zero(), Vote::new(1), Vote::new(3)]);
+    let a = Vote {
+        slot: 2,
+        lockout: 4,
+    };
+    assert!(Vote::new(2) < a);
+    assert_eq!(a.expiry(), 6);
+}
+
+#[test]
+fn test_merge() {
+    let mut a = Tower::default();
+    let mut b = Tower::default();
+    for slot in [1, 2, 3] {
+        let _ = a.apply(&Vote::new(slot));
+    }
+    for slot in [1, 2, 3, 4] {
+        let _ = b.apply(&Vote::new(slot));
+    }
+    //a is behind b on the same fork
+    let mut merged = a.clone();
+    assert_eq!(merged.merge(&b), Ok(()));
+    assert_eq!(merged, b);
+    assert_eq!(b.clone().merge(&a), Ok(()));
+    //c voted on 4 without 3, inside 3's lockout
+    let mut c = Tower::default();
+    for slot in [1, 2, 4] {
+        let _ = c.apply(&Vote::new(slot));
+    }
+    assert_eq!(a.clone().merge(&c), Err(TowerError::ConflictingFork));
+    //d's vote on 10 comes after all of a's votes expired
+    let mut d = Tower::default();
+    let _ = d.apply(&Vote::new(10));
+    let mut merged = a.clone();
+    assert_eq!(merged.merge(&d), Ok(()));
+    assert_eq!(merged, d);
+    //lockouts of different bases can't be compared
+    let mut merged = a.clone();
+    assert_eq!(
+        merged.merge(&Tower::with_base(3)),
+        Err(TowerError::BaseMismatch(2, 3))
+    );
+    assert_eq!(merged, a);
+}
+
+#[test]
+fn test_encode() {
+    let mut t = Tower::default();
+    for slot in 1..6 {
+        t.apply(&Vote::new(slot)).unwrap();
+    }
+    let bytes = t.encode();
+    assert_eq!(bytes[0], ENCODING_VERSION);
+    assert_eq!(Tower::decode(&bytes), Ok(t.clone()));
+    assert_eq!(Tower::decode(&[]), Err(DecodeError::Truncated));
+    assert_eq!(Tower::decode(&[2]), Err(DecodeError::Version(2)));
+    assert_eq!(Tower::decode(&[1, 1]), Err(DecodeError::Base(1)));
+    assert_eq!(
+        Tower::decode(&[1, 2, 0, 0, 17]),
+        Err(DecodeError::TooManyVotes(17))
+    );
+    assert_eq!(Tower::decode(&[1, 0xff]), Err(DecodeError::Truncated));
+    let mut overflow = vec![1];
+    overflow.extend([0xff; 9]);
+    overflow.push(0x02);
+    assert_eq!(Tower::decode(&overflow), Err(DecodeError::Overflow));
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert_eq!(Tower::decode(&trailing), Err(DecodeError::TrailingBytes(1)));
+    for i in 1..bytes.len() {
+        assert_eq!(Tower::decode(&bytes[..i]), Err(DecodeError::Truncated));
+    }
+}
+
+#[test]
+fn test_encode_fuzz() {
+    use crate::bank::splitmix64;
+    let mut seed = 0;
+    let mut next = || {
+        seed = splitmix64(seed);
+        //mix small and full width values to cover every varint length
+        seed >> (seed % 64)
+    };
+    for _ in 0..1000 {
+        //base^DEPTH has to fit in a u64
+        let mut t = Tower::with_base(next() % 14 + 2);
+        t.root = Vote {
+            slot: next(),
+            lockout: next(),
+        };
+        for _ in 0..next() % (DEPTH as u64 + 1) {
+            t.votes.push_front(Vote {
+                slot: next(),
+                lockout: next(),
+            });
+        }
+        assert_eq!(Tower::decode(&t.encode()), Ok(t));
+    }
+    //bases whose lockouts overflow are rejected, not built
+    let tail = &Tower::default().encode()[2..];
+    for base in [0, 1, 16, u64::MAX] {
+        let mut bytes = vec![ENCODING_VERSION];
+        let mut val = base;
+        while val >= 0x80 {
+            bytes.push(val as u8 | 0x80);
+            val >>= 7;
+        }
+        bytes.push(val as u8);
+        bytes.extend_from_slice(tail);
+        assert_eq!(Tower::decode(&bytes), Err(DecodeError::Base(base)));
+    }
+    assert!(Tower::valid_base(15));
+    //garbage never panics
+    for _ in 0..1000 {
+        let mut bytes = vec![ENCODING_VERSION];
+        bytes.extend((0..next() % 64).map(|_| next() as u8));
+        let _ = Tower::decode(&bytes);
+    }
+}
+
+#[test]
+fn test_expired_votes() {
+    let mut t = Tower::default();
+    for slot in 1..5 {
+        t.apply(&Vote::new(slot)).unwrap();
+    }
+    //4 expires after 6, 3 after 7, 2 after 10, 1 after 17
+    assert_eq!(t.expired_votes(6), vec![]);
+    assert_eq!(t.expired_votes(8), vec![Vote::new(4), t.votes[1]]);
+    //a large gap pops every vote
+    assert_eq!(t.expired_votes(1000).len(), 4);
+    t.apply(&Vote::new(1000)).unwrap();
+    assert_eq!(t.votes, VecDeque::from([Vote::new(1000)]));
+    assert_eq!(t.root, Vote::zero());
+}
+
+#[test]
+fn test_get_incrased_lockouts() {
+    let mut bank = Tower::default();
+    for i in 1..4 {
+        bank.apply(&Vote::new(i)).unwrap();
+    }
+    //nothing changed
+    assert!(bank.get_incrased_lockouts(0, &bank).is_empty());
+    //a vote on 4 doubles the lockouts on 1, 2 and 3, but 4 is new
+    let mut tower = bank.clone();
+    tower.apply(&Vote::new(4)).unwrap();
+    let expected: HashMap<Slot, u64> = [(1, 16), (2, 8), (3, 4)].into_iter().collect();
+    assert_eq!(bank.get_incrased_lockouts(0, &tower), expected);
+    //lockouts below skip_lockout are ignored
+    let expected: HashMap<Slot, u64> = [(1, 16), (2, 8)].into_iter().collect();
+    assert_eq!(bank.get_incrased_lockouts(8, &tower), expected);
+    //a vote on 6 expires 3, only 1 and 2 are left and neither grows
+    let mut tower = bank.clone();
+    tower.apply(&Vote::new(6)).unwrap();
+    assert_eq!(tower.lockout_at(3), None);
+    assert!(bank.get_incrased_lockouts(0, &tower).is_empty());
+    //a root the bank never saw a vote on is new too
+    let mut tower = bank.clone();
+    tower.root = Vote {
+        slot: 2,
+        lockout: 1 << DEPTH,
+    };
+    assert!(Tower::default().get_incrased_lockouts(0, &tower).is_empty());
+    //a bank that missed the votes on 2 and 3 sees 1 grow from 2 to 8
+    let mut missed = Tower::default();
+    missed.apply(&Vote::new(1)).unwrap();
+    let expected: HashMap<Slot, u64> = [(1, 8)].into_iter().collect();
+    assert_eq!(missed.get_incrased_lockouts(0, &bank), expected);
+}
+
+#[test]
+fn test_display() {
+    let mut t = Tower::default();
+    assert_eq!(t.display(), "root=0 votes=[]");
+    for i in 1..4 {
+        t.apply(&Vote::new(i)).unwrap();
+    }
+    assert_eq!(t.display(), "root=0 votes=[1:8,2:4,3:2]");
+    assert_eq!(format!("{}", t.latest_vote().unwrap()), "3:2");
+}
+
+#[cfg(feature = "proptest")]
+proptest::proptest! {
+    #[test]
+    fn test_apply_sequence(votes in vote_sequence(4 * DEPTH)) {
+        let mut t = Tower::default();
+        for v in &votes {
+            proptest::prop_assert_eq!(t.apply(v), Ok(()));
+        }
+        proptest::prop_assert_eq!(t.validate(), Ok(()));
+        //slots increase from the oldest vote to the latest
+        let slots: Vec<_> = t.votes.iter().rev().map(|v| v.slot).collect();
+        proptest::prop_assert!(slots.windows(2).all(|w| w[0] < w[1]));
+        proptest::prop_assert!(t.votes.iter().all(|v| v.lockout.is_power_of_two()));
+        proptest::prop_assert!(t.root.lockout.is_power_of_two());
+    }
+
+    #[test]
+    fn test_arbitrary_tower(t: Tower, v: Vote) {
+        proptest::prop_assert_eq!(t.validate(), Ok(()));
+        proptest::prop_assert_eq!(Tower::decode(&t.encode()), Ok(t.clone()));
+        //any arbitrary vote applies or is rejected, and keeps the tower valid
+        let mut applied = t.clone();
+        if applied.apply(&v).is_ok() {
+            proptest::prop_assert_eq!(applied.validate(), Ok(()));
+            proptest::prop_assert_eq!(applied.latest_vote(), Some(&v));
+        } else {
+            proptest::prop_assert_eq!(applied, t);
+        }
+    }
+}
+
+#[test]
+fn test_verify_tower() {
+    use crate::bank::Block;
+    let mut banks = Banks::default();
+    for (slot, parent) in [(1, 0), (2, 1), (3, 2), (4, 1), (5, 3)] {
+        banks
+            .apply(&Block {
+                slot,
+                parent,
+                votes: vec![],
+            })
+            .unwrap();
+    }
+    let mut tower = Tower::default();
+    for slot in [1, 2, 3, 5] {
+        tower.apply(&Vote::new(slot)).unwrap();
+    }
+    assert_eq!(verify_tower(&tower, &banks), Ok(()));
+    assert_eq!(verify_tower(&Tower::default(), &banks), Ok(()));
+    //2 and 3 aren't ancestors of 4
+    let mut forked = tower.clone();
+    forked.votes[0].slot = 4;
+    assert_eq!(
+        verify_tower(&forked, &banks),
+        Err(vec![TowerError::OffFork(3), TowerError::OffFork(2)])
+    );
+    let mut unknown = tower.clone();
+    unknown.votes[0].slot = 9;
+    unknown.votes[1].lockout = 1;
+    assert_eq!(
+        verify_tower(&unknown, &banks),
+        Err(vec![
+            TowerError::UnknownSlot(9),
+            TowerError::LockoutOrder(3),
+            TowerError::OffFork(3),
+            TowerError::OffFork(2),
+            TowerError::OffFork(1),
+        ])
+    );
+    //the root is on the other fork
+    let mut rooted = tower.clone();
+    rooted.root.slot = 4;
+    assert_eq!(
+        verify_tower(&rooted, &banks).unwrap_err()[..2],
+        [TowerError::RootNotAncestor(5), TowerError::RootRegression]
+    );
+}
+
+#[test]
+fn test_would_root() {
+    let mut t = Tower::default();
+    for slot in 1..=40 {
+        let vote = Vote::new(slot);
+        let root = t.root;
+        let predicted = t.would_root(&vote);
+        t.apply(&vote).unwrap();
+        assert_eq!(predicted, (t.root != root).then_some(t.root.slot));
+    }
+    //on consecutive slots the tower keeps DEPTH - 1 votes above the root
+    assert_eq!(t.root.slot, 40 - (DEPTH as Slot - 1));
+    assert_eq!(t.would_root(&Vote::new(41)), Some(26));
+    //a vote that skips past the lockouts pops votes instead
+    assert_eq!(t.would_root(&Vote::new(1000)), None);
+    assert_eq!(t.would_root(&Vote::new(40)), None);
+    assert_eq!(
+        t.would_root(&Vote {
+            slot: 41,
+            lockout: 4
+        }),
+        None
+    );
+}
+
+#[test]
+fn test_vote_stack_vec() {
+    let mut t = Tower::default();
+    for slot in 1..4 {
+        t.apply(&Vote::new(slot)).unwrap();
+    }
+    let votes: Vec<Vote> = t.votes.into();
+    assert_eq!(votes[0], Vote::new(3));
+    assert_eq!(VoteStack::try_from(votes), Ok(t.votes));
+    assert!(VoteStack::try_from(vec![Vote::new(1); DEPTH + 1]).is_err());
+}